@@ -0,0 +1,78 @@
+//! Unit abilities. Balance numbers live in the `AbilityDefinition` table so
+//! they can change without a republish; `GameState::use_ability` applies them.
+
+use crate::{AbilityKind, AbilitySpec, UnitClass};
+
+/// Most movement a Dash can add, so a bad edit can't send units across the map
+pub const MAX_DASH_POWER: i32 = 8;
+
+/// Most turns a Stun can take away, so a bad edit can't lock a unit down for good
+pub const MAX_STUN_TURNS: i32 = 1;
+
+/// Whether using an ability takes the unit's attack for the turn
+pub fn uses_attack(kind: AbilityKind) -> bool {
+    matches!(kind, AbilityKind::Shot | AbilityKind::Heal | AbilityKind::Poison | AbilityKind::Stun)
+}
+
+/// Whether an ability is aimed at another unit rather than the user
+pub fn needs_target(kind: AbilityKind) -> bool {
+    matches!(kind, AbilityKind::Shot | AbilityKind::Heal | AbilityKind::Poison | AbilityKind::Stun)
+}
+
+/// Check an ability definition before it goes into the table
+pub fn validate_ability(ability: &AbilitySpec) -> Result<(), String> {
+    if ability.name.is_empty() {
+        return Err("ability needs a name".to_string());
+    }
+    if ability.power <= 0 {
+        return Err(format!("{}: power must be positive", ability.name));
+    }
+    if needs_target(ability.kind) && ability.range == 0 {
+        return Err(format!("{}: targeted abilities need a range of at least 1", ability.name));
+    }
+    if ability.kind == AbilityKind::Dash && ability.power > MAX_DASH_POWER {
+        return Err(format!("{}: Dash power can be at most {}", ability.name, MAX_DASH_POWER));
+    }
+    if ability.kind == AbilityKind::Stun && ability.power > MAX_STUN_TURNS {
+        return Err(format!("{}: Stun power can be at most {}", ability.name, MAX_STUN_TURNS));
+    }
+    if ability.cooldown == 0 {
+        return Err(format!("{}: cooldown must be at least 1 turn", ability.name));
+    }
+    Ok(())
+}
+
+/// Abilities seeded on init
+pub fn default_abilities() -> Vec<(UnitClass, AbilitySpec)> {
+    let ability = |name: &str, kind, range, power, cooldown| AbilitySpec {
+        name: name.to_string(),
+        kind,
+        range,
+        power,
+        cooldown,
+    };
+    vec![
+        (UnitClass::Infantry, ability("first_aid", AbilityKind::Heal, 1, 25, 3)),
+        (UnitClass::Infantry, ability("fortify", AbilityKind::Fortify, 0, 1, 2)),
+        (UnitClass::Tank, ability("shell", AbilityKind::Shot, 3, 50, 2)),
+        (UnitClass::Scout, ability("dash", AbilityKind::Dash, 0, 4, 2)),
+        (UnitClass::Scout, ability("poison_dart", AbilityKind::Poison, 2, 10, 3)),
+        (UnitClass::Tank, ability("concussion", AbilityKind::Stun, 1, 1, 4)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid_and_bad_edits_are_caught() {
+        for (_, ability) in default_abilities() {
+            assert_eq!(validate_ability(&ability), Ok(()));
+        }
+        let (_, shell) = default_abilities().remove(2);
+        assert!(validate_ability(&AbilitySpec { range: 0, ..shell.clone() }).is_err());
+        assert!(validate_ability(&AbilitySpec { power: 0, ..shell.clone() }).is_err());
+        assert!(validate_ability(&AbilitySpec { cooldown: 0, ..shell }).is_err());
+    }
+}
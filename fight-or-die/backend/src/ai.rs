@@ -0,0 +1,216 @@
+//! Computer opponent. The bot picks one action at a time from a `GameState`,
+//! seeing only what its own units can see, and every action it picks goes
+//! through the same `GameState` rules as a human's.
+
+use crate::rules::{calculate_distance, find_path, get_class_stats, get_terrain_defense};
+use crate::state::{GameState, Seat, UnitState};
+use crate::{BotDifficulty, Position};
+
+/// Below this share of max HP, Hard bots pull units back to defensive terrain
+pub const RETREAT_HP_PERCENT: i32 = 35;
+
+/// What the bot wants to do next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BotAction {
+    Move { unit_id: u32, to: Position },
+    Attack { attacker_id: u32, target_id: u32 },
+}
+
+/// The bot's next action this turn, or `None` when it should end its turn.
+/// Units act in order: attack if anything is in range, otherwise move.
+/// Every action spends movement or an attack, so a turn always runs out.
+pub fn next_action(state: &GameState, seat: Seat, difficulty: BotDifficulty) -> Option<BotAction> {
+    let visible = state.visible_tiles(seat);
+    let enemies: Vec<&UnitState> = state
+        .units
+        .iter()
+        .filter(|u| state.teams[u.owner] != state.teams[seat] && u.hp > 0 && visible.contains(&(u.x, u.y)))
+        .collect();
+
+    for unit in state.units.iter().filter(|u| u.owner == seat && u.hp > 0) {
+        if !unit.has_attacked {
+            if let Some(target_id) = choose_target(state, seat, unit, difficulty, &enemies) {
+                return Some(BotAction::Attack { attacker_id: unit.id, target_id });
+            }
+        }
+        if unit.movement_left > 0 {
+            if let Some(to) = choose_destination(state, seat, unit, difficulty, &enemies) {
+                return Some(BotAction::Move { unit_id: unit.id, to });
+            }
+        }
+    }
+    None
+}
+
+/// Enemy to attack from where the unit stands. Easy hits whatever it finds
+/// first, Normal focuses the target left with the least HP, and Hard goes
+/// for kills, then the best trade after counter-attacks, and won't throw a
+/// unit away on an attack that doesn't kill.
+fn choose_target(
+    state: &GameState,
+    seat: Seat,
+    unit: &UnitState,
+    difficulty: BotDifficulty,
+    enemies: &[&UnitState],
+) -> Option<u32> {
+    let options = enemies
+        .iter()
+        .filter_map(|e| state.preview_attack(seat, unit.id, e.id).ok().map(|outcome| (e.id, outcome)));
+    match difficulty {
+        BotDifficulty::Easy => options.map(|(id, _)| id).next(),
+        BotDifficulty::Normal => options.min_by_key(|(_, o)| o.target_hp_after).map(|(id, _)| id),
+        BotDifficulty::Hard => options
+            .filter(|(_, o)| o.target_hp_after == 0 || o.attacker_hp_after > 0)
+            .max_by_key(|(_, o)| (o.target_hp_after == 0, o.damage - o.counter_damage))
+            .map(|(id, _)| id),
+    }
+}
+
+/// Tile to move to, or `None` if staying put is at least as good.
+///
+/// Units close in on a goal: a visible enemy (the nearest for Easy, the
+/// weakest for Normal and Hard), otherwise the nearest capture point its team
+/// doesn't hold, otherwise the corner opposite its own. Normal and Hard break ties
+/// towards Forest and Mountain tiles. Hard bots send badly hurt units to the
+/// best defensive tile in reach, as far from the enemy as they can.
+fn choose_destination(
+    state: &GameState,
+    seat: Seat,
+    unit: &UnitState,
+    difficulty: BotDifficulty,
+    enemies: &[&UnitState],
+) -> Option<Position> {
+    let stats = get_class_stats(unit.class);
+    let retreating =
+        difficulty == BotDifficulty::Hard && !enemies.is_empty() && unit.hp * 100 < stats.max_hp * RETREAT_HP_PERCENT;
+    let (goal, reach) = goal_for(state, seat, unit, difficulty, enemies);
+
+    let score = |x: u8, y: u8| -> i32 {
+        let defense = match difficulty {
+            BotDifficulty::Easy => 0,
+            _ => get_terrain_defense(state.terrain[y as usize][x as usize]),
+        };
+        if retreating {
+            let nearest = enemies.iter().map(|e| calculate_distance(x, y, e.x, e.y)).min().unwrap_or(0);
+            defense * 100 + nearest as i32
+        } else {
+            let gap = calculate_distance(x, y, goal.0, goal.1).saturating_sub(reach) as i32;
+            -gap * 10 + defense
+        }
+    };
+
+    // Like a player, the bot only steers around units it knows about
+    let blocked = state.known_unit_tiles(seat, unit.id);
+    let mut best = None;
+    let mut best_score = score(unit.x, unit.y);
+    for y in 0..state.terrain.len() as u8 {
+        for x in 0..state.terrain[0].len() as u8 {
+            if (x, y) == (unit.x, unit.y) || blocked.contains(&(x, y)) {
+                continue;
+            }
+            let tile_score = score(x, y);
+            if tile_score > best_score
+                && find_path(&state.terrain, &blocked, (unit.x, unit.y), (x, y), unit.movement_left).is_some()
+            {
+                best = Some(Position { x, y });
+                best_score = tile_score;
+            }
+        }
+    }
+    best
+}
+
+/// Tile a unit is heading for and how close it needs to get
+fn goal_for(
+    state: &GameState,
+    seat: Seat,
+    unit: &UnitState,
+    difficulty: BotDifficulty,
+    enemies: &[&UnitState],
+) -> ((u8, u8), u8) {
+    let distance = |x: u8, y: u8| calculate_distance(unit.x, unit.y, x, y);
+    let target = match difficulty {
+        BotDifficulty::Easy => enemies.iter().min_by_key(|e| distance(e.x, e.y)),
+        _ => enemies.iter().min_by_key(|e| (e.hp, distance(e.x, e.y))),
+    };
+    if let Some(target) = target {
+        return ((target.x, target.y), get_class_stats(unit.class).attack_range);
+    }
+
+    let capture_point = state
+        .capture_points
+        .iter()
+        .filter(|p| p.owner.map(|owner| state.teams[owner]) != Some(state.teams[seat]))
+        .min_by_key(|p| distance(p.x, p.y));
+    if let Some(point) = capture_point {
+        return ((point.x, point.y), 0);
+    }
+
+    // Seats start top-left, bottom-right, top-right and bottom-left
+    let (right, bottom) = (state.terrain[0].len() as u8 - 1, state.terrain.len() as u8 - 1);
+    let far_corner = match seat {
+        0 => (right, bottom),
+        1 => (0, 0),
+        2 => (0, bottom),
+        _ => (right, 0),
+    };
+    (far_corner, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, ObjectiveType, SpawnedUnit, TerrainType, UnitClass};
+
+    fn spawned(unit_id: u32, class: UnitClass, x: u8, y: u8) -> SpawnedUnit {
+        SpawnedUnit { unit_id, class, position: Position { x, y }, facing: Direction::South }
+    }
+
+    fn game(terrain: Vec<Vec<TerrainType>>, bot: &[SpawnedUnit], enemy: &[SpawnedUnit]) -> GameState {
+        let objectives = vec![vec![ObjectiveType::None; terrain[0].len()]; terrain.len()];
+        let mut state = GameState::new(terrain, objectives);
+        state.join(0, bot).unwrap();
+        state.join(1, enemy).unwrap();
+        state
+    }
+
+    #[test]
+    fn normal_focuses_the_weakest_target() {
+        let terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        let enemy = [spawned(2, UnitClass::Infantry, 2, 1), spawned(3, UnitClass::Scout, 2, 3)];
+        let mut state = game(terrain, &[spawned(1, UnitClass::Tank, 2, 2)], &enemy);
+        state.units[2].hp = 15;
+
+        let easy = next_action(&state, 0, BotDifficulty::Easy);
+        assert_eq!(easy, Some(BotAction::Attack { attacker_id: 1, target_id: 2 }));
+        let normal = next_action(&state, 0, BotDifficulty::Normal);
+        assert_eq!(normal, Some(BotAction::Attack { attacker_id: 1, target_id: 3 }));
+    }
+
+    #[test]
+    fn closes_in_then_ends_turn() {
+        let terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        let mut state = game(terrain, &[spawned(1, UnitClass::Infantry, 0, 1)], &[spawned(2, UnitClass::Infantry, 0, 4)]);
+
+        let action = next_action(&state, 0, BotDifficulty::Normal);
+        assert_eq!(action, Some(BotAction::Move { unit_id: 1, to: Position { x: 0, y: 3 } }));
+        state.move_unit(0, 1, Position { x: 0, y: 3 }).unwrap();
+        assert_eq!(next_action(&state, 0, BotDifficulty::Normal), Some(BotAction::Attack { attacker_id: 1, target_id: 2 }));
+        state.attack(0, 1, 2).unwrap();
+        assert_eq!(next_action(&state, 0, BotDifficulty::Normal), None);
+    }
+
+    #[test]
+    fn hard_pulls_hurt_units_back_to_mountains() {
+        let mut terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        terrain[0][2] = TerrainType::Mountain;
+        let mut state = game(terrain, &[spawned(1, UnitClass::Scout, 2, 2)], &[spawned(2, UnitClass::Tank, 2, 4)]);
+        state.units[0].hp = 20;
+        state.units[0].has_attacked = true;
+
+        let action = next_action(&state, 0, BotDifficulty::Hard);
+        assert_eq!(action, Some(BotAction::Move { unit_id: 1, to: Position { x: 2, y: 0 } }));
+        let normal = next_action(&state, 0, BotDifficulty::Normal);
+        assert_eq!(normal, Some(BotAction::Move { unit_id: 1, to: Position { x: 2, y: 3 } }));
+    }
+}
@@ -0,0 +1,560 @@
+//! Board layouts: seeded generation, built-in layouts, objectives, spawn
+//! positions and the terrain/objective string formats stored on `Board`.
+
+use crate::terrain::terrain_properties;
+use crate::{ObjectiveType, TerrainType, MAX_BOARD_SIZE, MAX_PLAYERS, MAX_SQUAD_SIZE, MIN_BOARD_SIZE};
+
+// ===== CONSTANTS =====
+
+/// Version written by `serialize_board`. Version 1 is the original
+/// headerless 25-character 5x5 string; version 2 added the header and only
+/// knows P/F/M/S tiles.
+const BOARD_FORMAT_VERSION: u8 = 3;
+
+/// Generated layouts that wall the spawns off from each other are rerolled
+/// this many times before a path is carved through instead
+const MAX_GENERATION_ATTEMPTS: u32 = 16;
+
+/// Starting tiles for player 1, filled in order. The other seats get the
+/// same slots mirrored into their own corner (see `spawn_positions`).
+const SPAWN_SLOTS: [(u8, u8); MAX_SQUAD_SIZE as usize] = [(0, 0), (2, 0), (0, 2), (1, 0), (0, 1)];
+
+// ===== GENERATION =====
+
+/// Terrain mix for generated boards, in percent of the tiles outside the spawn zones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainRatios {
+    pub forest_percent: u32,
+    pub mountain_percent: u32,
+    pub swamp_percent: u32,
+    pub water_percent: u32,
+    pub ruins_percent: u32,
+}
+
+/// Terrain mix used by `generate_board`. Rebalance here.
+pub const TERRAIN_RATIOS: TerrainRatios = TerrainRatios {
+    forest_percent: 24,
+    mountain_percent: 12,
+    swamp_percent: 6,
+    water_percent: 4,
+    ruins_percent: 4,
+};
+
+/// Small deterministic PRNG (SplitMix64) so a board can be rebuilt from its seed
+struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+    
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    
+    /// Uniform-enough value in 0..n for board-sized ranges
+    fn next_below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+    
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.next_below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// How a generated board is mirrored so every starting corner is equal
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symmetry {
+    Rotational, // 180 degree rotation around the center
+    Diagonal,   // Reflection across the anti-diagonal (square boards only)
+    FourWay,    // Reflection across both center lines, for three or four seats
+}
+
+/// Tiles that `symmetry` maps (x, y) onto, starting with (x, y) itself and
+/// without repeats. Two-way symmetries map (0,0) to the far corner, `FourWay`
+/// to every corner.
+fn mirror_tiles(symmetry: Symmetry, width: u8, height: u8, x: u8, y: u8) -> Vec<(u8, u8)> {
+    let (mx, my) = (width - 1 - x, height - 1 - y);
+    let images = match symmetry {
+        Symmetry::Rotational => vec![(x, y), (mx, my)],
+        Symmetry::Diagonal => vec![(x, y), (height - 1 - y, width - 1 - x)],
+        Symmetry::FourWay => vec![(x, y), (mx, y), (x, my), (mx, my)],
+    };
+    let mut tiles = Vec::new();
+    for tile in images {
+        if !tiles.contains(&tile) {
+            tiles.push(tile);
+        }
+    }
+    tiles
+}
+
+/// The original hand-placed layout, centered on the board
+pub fn classic_board(width: u8, height: u8) -> Vec<Vec<TerrainType>> {
+    let mut board = vec![vec![TerrainType::Plains; width as usize]; height as usize];
+    let cx = (width / 2) as usize;
+    let cy = (height / 2) as usize;
+    
+    // Add forests for medium defense positions
+    board[cy - 1][cx - 1] = TerrainType::Forest;
+    board[cy + 1][cx + 1] = TerrainType::Forest;
+    board[cy][cx - 1] = TerrainType::Forest;
+    board[cy - 1][cx + 1] = TerrainType::Forest;
+    board[cy + 1][cx - 1] = TerrainType::Forest;
+    
+    // Center mountain provides strategic high ground
+    board[cy][cx] = TerrainType::Mountain;
+    
+    board
+}
+
+/// Generate a symmetric board from a seed for `seats` players. The same seed,
+/// size and seat count always produce the same board, terrain follows
+/// `TERRAIN_RATIOS`, spawn zones stay Plains and every starting corner is
+/// connected to the others without crossing a Mountain or Water. Boards for
+/// more than two seats are mirrored both ways so all four corners are equal.
+pub fn generate_board(width: u8, height: u8, seed: u64, seats: usize) -> Vec<Vec<TerrainType>> {
+    let mut rng = SeededRng::new(seed);
+    let symmetry = if seats > 2 {
+        Symmetry::FourWay
+    } else if width == height && rng.next_below(2) == 1 {
+        Symmetry::Diagonal
+    } else {
+        Symmetry::Rotational
+    };
+    
+    // Spawn zones for every seat stay clear
+    let spawn_tiles: Vec<(u8, u8)> = (0..seats)
+        .flat_map(|seat| spawn_positions(width, height, MAX_SQUAD_SIZE, seat))
+        .flat_map(|(x, y)| mirror_tiles(symmetry, width, height, x, y))
+        .collect();
+    
+    // Group the remaining tiles with their mirror tiles (center tiles may stand alone)
+    let mut seen = vec![vec![false; width as usize]; height as usize];
+    let mut groups: Vec<Vec<(u8, u8)>> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if seen[y as usize][x as usize] || spawn_tiles.contains(&(x, y)) {
+                continue;
+            }
+            let group = mirror_tiles(symmetry, width, height, x, y);
+            for &(mx, my) in &group {
+                seen[my as usize][mx as usize] = true;
+            }
+            groups.push(group);
+        }
+    }
+    
+    let open_tiles: u32 = groups.iter().map(|g| g.len() as u32).sum();
+    let mountain_target = open_tiles * TERRAIN_RATIOS.mountain_percent / 100;
+    let forest_target = open_tiles * TERRAIN_RATIOS.forest_percent / 100;
+    let swamp_target = open_tiles * TERRAIN_RATIOS.swamp_percent / 100;
+    let water_target = open_tiles * TERRAIN_RATIOS.water_percent / 100;
+    let ruins_target = open_tiles * TERRAIN_RATIOS.ruins_percent / 100;
+    
+    let mut board = vec![vec![TerrainType::Plains; width as usize]; height as usize];
+    for _ in 0..MAX_GENERATION_ATTEMPTS {
+        board = vec![vec![TerrainType::Plains; width as usize]; height as usize];
+        rng.shuffle(&mut groups);
+        
+        let mut mountains = 0;
+        let mut forests = 0;
+        let mut swamps = 0;
+        let mut water = 0;
+        let mut ruins = 0;
+        for group in &groups {
+            let size = group.len() as u32;
+            let terrain = if mountains + size <= mountain_target {
+                mountains += size;
+                TerrainType::Mountain
+            } else if forests + size <= forest_target {
+                forests += size;
+                TerrainType::Forest
+            } else if swamps + size <= swamp_target {
+                swamps += size;
+                TerrainType::Swamp
+            } else if water + size <= water_target {
+                water += size;
+                TerrainType::Water
+            } else if ruins + size <= ruins_target {
+                ruins += size;
+                TerrainType::Ruins
+            } else {
+                continue;
+            };
+            for &(x, y) in group {
+                board[y as usize][x as usize] = terrain;
+            }
+        }
+        
+        if spawns_connected(&board, seats) {
+            return board;
+        }
+    }
+    
+    // Every attempt walled the spawns off - clear a route along the edges
+    for x in 0..width {
+        clear_blocker(&mut board, symmetry, x, 0);
+    }
+    for y in 0..height {
+        clear_blocker(&mut board, symmetry, width - 1, y);
+    }
+    board
+}
+
+/// Whether generation treats a tile as a wall between the spawns
+fn blocks_route(terrain: TerrainType) -> bool {
+    terrain == TerrainType::Mountain || !terrain_properties(terrain).passable
+}
+
+/// Turn a Mountain or Water tile (and its mirror tiles) back into Plains
+fn clear_blocker(board: &mut [Vec<TerrainType>], symmetry: Symmetry, x: u8, y: u8) {
+    let height = board.len() as u8;
+    let width = board[0].len() as u8;
+    for (tx, ty) in mirror_tiles(symmetry, width, height, x, y) {
+        if blocks_route(board[ty as usize][tx as usize]) {
+            board[ty as usize][tx as usize] = TerrainType::Plains;
+        }
+    }
+}
+
+/// Breadth-first search from the top-left corner to every other seat's
+/// corner without stepping on Mountains or Water
+fn spawns_connected(board: &[Vec<TerrainType>], seats: usize) -> bool {
+    let height = board.len();
+    let width = board[0].len();
+    let mut visited = vec![vec![false; width]; height];
+    let mut queue = std::collections::VecDeque::new();
+    visited[0][0] = true;
+    queue.push_back((0usize, 0usize));
+    
+    while let Some((x, y)) = queue.pop_front() {
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbours {
+            if nx < width && ny < height && !visited[ny][nx] && !blocks_route(board[ny][nx]) {
+                visited[ny][nx] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    (1..seats).all(|seat| {
+        let (x, y) = spawn_positions(width as u8, height as u8, 1, seat)[0];
+        visited[y as usize][x as usize]
+    })
+}
+
+fn terrain_to_char(terrain: TerrainType) -> char {
+    match terrain {
+        TerrainType::Plains => 'P',
+        TerrainType::Forest => 'F',
+        TerrainType::Mountain => 'M',
+        TerrainType::Swamp => 'S',
+        TerrainType::Water => 'W',
+        TerrainType::River => '~',
+        TerrainType::Road => 'R',
+        TerrainType::Ruins => 'U',
+    }
+}
+
+fn terrain_from_char(c: char) -> Option<TerrainType> {
+    match c {
+        'P' => Some(TerrainType::Plains),
+        'F' => Some(TerrainType::Forest),
+        'M' => Some(TerrainType::Mountain),
+        'S' => Some(TerrainType::Swamp),
+        'W' => Some(TerrainType::Water),
+        '~' => Some(TerrainType::River),
+        'R' => Some(TerrainType::Road),
+        'U' => Some(TerrainType::Ruins),
+        _ => None,
+    }
+}
+
+/// Serialize a board as "v3:<width>x<height>:<tiles>"
+pub fn serialize_board(board: &[Vec<TerrainType>]) -> String {
+    let height = board.len();
+    let width = board.first().map_or(0, |row| row.len());
+    let mut result = format!("v{}:{}x{}:", BOARD_FORMAT_VERSION, width, height);
+    for row in board {
+        for terrain in row {
+            result.push(terrain_to_char(*terrain));
+        }
+    }
+    result
+}
+
+fn objective_to_char(objective: ObjectiveType) -> char {
+    match objective {
+        ObjectiveType::None => '.',
+        ObjectiveType::CapturePoint => 'C',
+        ObjectiveType::HealingStation => 'H',
+    }
+}
+
+fn objective_from_char(c: char) -> Option<ObjectiveType> {
+    match c {
+        '.' => Some(ObjectiveType::None),
+        'C' => Some(ObjectiveType::CapturePoint),
+        'H' => Some(ObjectiveType::HealingStation),
+        _ => None,
+    }
+}
+
+pub fn serialize_objectives(objectives: &[Vec<ObjectiveType>]) -> String {
+    objectives.iter().flatten().map(|o| objective_to_char(*o)).collect()
+}
+
+/// Parse objective data for a board of the given size. Boards saved before
+/// objectives existed have an empty string and get none.
+pub fn deserialize_objectives(data: &str, width: u8, height: u8) -> Vec<Vec<ObjectiveType>> {
+    let mut objectives = vec![vec![ObjectiveType::None; width as usize]; height as usize];
+    let chars: Vec<char> = data.chars().collect();
+    for (i, row) in objectives.iter_mut().enumerate() {
+        for (j, objective) in row.iter_mut().enumerate() {
+            let idx = i * width as usize + j;
+            if idx < chars.len() {
+                *objective = objective_from_char(chars[idx]).unwrap_or(ObjectiveType::None);
+            }
+        }
+    }
+    objectives
+}
+
+/// Standard objective layout: a capture point in the center, one healing
+/// station in front of each spawn and, on 7x7 and up, two more capture points
+/// in the neutral corners. Games for more than two seats have no neutral
+/// corners, so every corner gets a healing station and the extra capture
+/// points move to the middle of each edge. Symmetric under both generator
+/// symmetries.
+pub fn default_objectives(width: u8, height: u8, seats: usize) -> Vec<Vec<ObjectiveType>> {
+    let (w, h) = (width as usize, height as usize);
+    let mut objectives = vec![vec![ObjectiveType::None; w]; h];
+    objectives[h / 2][w / 2] = ObjectiveType::CapturePoint;
+    objectives[1][1] = ObjectiveType::HealingStation;
+    objectives[h - 2][w - 2] = ObjectiveType::HealingStation;
+    if seats > 2 {
+        objectives[1][w - 2] = ObjectiveType::HealingStation;
+        objectives[h - 2][1] = ObjectiveType::HealingStation;
+        objectives[1][w / 2] = ObjectiveType::CapturePoint;
+        objectives[h - 2][w / 2] = ObjectiveType::CapturePoint;
+        objectives[h / 2][1] = ObjectiveType::CapturePoint;
+        objectives[h / 2][w - 2] = ObjectiveType::CapturePoint;
+    } else if width >= 7 && height >= 7 {
+        objectives[h - 2][1] = ObjectiveType::CapturePoint;
+        objectives[1][w - 2] = ObjectiveType::CapturePoint;
+    }
+    objectives
+}
+
+/// Split a v2 or later terrain string into (width, height, tiles). Versions
+/// since 2 share the header and only add tile characters.
+fn parse_board_header(data: &str) -> Option<(usize, usize, &str)> {
+    let (version, rest) = data.strip_prefix('v')?.split_once(':')?;
+    if !(2..=BOARD_FORMAT_VERSION).contains(&version.parse().ok()?) {
+        return None;
+    }
+    let (dims, tiles) = rest.split_once(':')?;
+    let (width, height) = dims.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?, tiles))
+}
+
+/// Parse a terrain string. Headerless strings are the version 1 format:
+/// 25 characters for a 5x5 board.
+pub fn deserialize_board(data: &str) -> Vec<Vec<TerrainType>> {
+    let (width, height, tiles) = parse_board_header(data).unwrap_or((5, 5, data));
+    let mut board = vec![vec![TerrainType::Plains; width]; height];
+    let chars: Vec<char> = tiles.chars().collect();
+    
+    for (i, row) in board.iter_mut().enumerate() {
+        for (j, tile) in row.iter_mut().enumerate() {
+            let idx = i * width + j;
+            if idx < chars.len() {
+                *tile = terrain_from_char(chars[idx]).unwrap_or(TerrainType::Plains);
+            }
+        }
+    }
+    
+    board
+}
+
+/// Check that a raw tile string (see `terrain_from_char`) describes a
+/// playable board: every seat's spawn tiles must be passable
+pub fn validate_map_tiles(width: u8, height: u8, tiles: &str) -> Result<(), String> {
+    let sizes = MIN_BOARD_SIZE..=MAX_BOARD_SIZE;
+    if !sizes.contains(&width) || !sizes.contains(&height) {
+        return Err(format!("Map must be between {0}x{0} and {1}x{1}", MIN_BOARD_SIZE, MAX_BOARD_SIZE));
+    }
+    if tiles.chars().count() != width as usize * height as usize {
+        return Err(format!("Expected {} tiles, got {}", width as usize * height as usize, tiles.chars().count()));
+    }
+    if let Some(c) = tiles.chars().find(|c| terrain_from_char(*c).is_none()) {
+        return Err(format!("Unknown terrain tile: {}", c));
+    }
+    let board = tiles_to_board(width, tiles);
+    for seat in 0..MAX_PLAYERS as usize {
+        for (x, y) in spawn_positions(width, height, MAX_SQUAD_SIZE, seat) {
+            if !terrain_properties(board[y as usize][x as usize]).passable {
+                return Err(format!("Spawn tile {},{} is impassable", x, y));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that a raw ./C/H objective string matches the board size
+pub fn validate_map_objectives(width: u8, height: u8, objectives: &str) -> Result<(), String> {
+    if objectives.chars().count() != width as usize * height as usize {
+        return Err(format!("Expected {} objective tiles, got {}", width as usize * height as usize, objectives.chars().count()));
+    }
+    if let Some(c) = objectives.chars().find(|c| objective_from_char(*c).is_none()) {
+        return Err(format!("Unknown objective tile: {}", c));
+    }
+    Ok(())
+}
+
+/// Turn a raw tile string into a board grid
+pub fn tiles_to_board(width: u8, tiles: &str) -> Vec<Vec<TerrainType>> {
+    let row: Vec<TerrainType> = tiles.chars().map(|c| terrain_from_char(c).unwrap_or(TerrainType::Plains)).collect();
+    row.chunks(width as usize).map(|r| r.to_vec()).collect()
+}
+
+/// Starting positions for the squad in a seat. Player 1 spreads out from the
+/// top-left corner and player 2 mirrors that layout from the bottom-right
+/// one; players 3 and 4 take the top-right and bottom-left corners.
+pub fn spawn_positions(width: u8, height: u8, squad_size: u8, seat: usize) -> Vec<(u8, u8)> {
+    SPAWN_SLOTS
+        .iter()
+        .take(squad_size as usize)
+        .map(|&(x, y)| match seat {
+            0 => (x, y),
+            1 => (width - 1 - x, height - 1 - y),
+            2 => (width - 1 - x, y),
+            _ => (x, height - 1 - y),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_legacy_board_strings() {
+        let board = deserialize_board("PPPPPPFPFPPFMPPPFPFPPPPPP");
+        assert_eq!(board.len(), 5);
+        assert_eq!(board[0].len(), 5);
+        assert_eq!(board[2][2], TerrainType::Mountain);
+        assert_eq!(board[1][1], TerrainType::Forest);
+    }
+
+    #[test]
+    fn board_strings_round_trip() {
+        let board = generate_board(9, 7, 42, 2);
+        let data = serialize_board(&board);
+        assert!(data.starts_with("v3:9x7:"));
+        assert_eq!(deserialize_board(&data), board);
+        assert_eq!(deserialize_board("v2:5x5:PPPPPPFPFPPFMPPPFPFPPPPPP"), deserialize_board("PPPPPPFPFPPFMPPPFPFPPPPPP"));
+
+        let objectives = default_objectives(9, 7, 2);
+        assert_eq!(deserialize_objectives(&serialize_objectives(&objectives), 9, 7), objectives);
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        for seed in 0..20 {
+            assert_eq!(generate_board(7, 7, seed, 2), generate_board(7, 7, seed, 2));
+        }
+        assert_ne!(generate_board(9, 9, 1, 2), generate_board(9, 9, 2, 2));
+    }
+
+    #[test]
+    fn generated_boards_are_fair() {
+        for seed in 0..200 {
+            for size in [5, 7, 9] {
+                let board = generate_board(size, size, seed, 2);
+                let rotational = (0..size).all(|y| {
+                    (0..size).all(|x| board[y as usize][x as usize] == board[(size - 1 - y) as usize][(size - 1 - x) as usize])
+                });
+                let diagonal = (0..size).all(|y| {
+                    (0..size).all(|x| board[y as usize][x as usize] == board[(size - 1 - x) as usize][(size - 1 - y) as usize])
+                });
+                assert!(rotational || diagonal, "seed {} size {} is not symmetric", seed, size);
+                assert!(spawns_connected(&board, 2), "seed {} size {} walls off a spawn", seed, size);
+
+                for seat in 0..2 {
+                    for (x, y) in spawn_positions(size, size, MAX_SQUAD_SIZE, seat) {
+                        assert_eq!(board[y as usize][x as usize], TerrainType::Plains);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn four_seat_boards_clear_every_corner() {
+        for seed in 0..200 {
+            for size in [7, 9] {
+                let board = generate_board(size, size, seed, 4);
+                assert!(spawns_connected(&board, 4), "seed {} size {} walls off a spawn", seed, size);
+                for seat in 0..4 {
+                    for (x, y) in spawn_positions(size, size, MAX_SQUAD_SIZE, seat) {
+                        assert_eq!(board[y as usize][x as usize], TerrainType::Plains);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boards_for_more_than_two_seats_mirror_both_ways() {
+        for seed in 0..200 {
+            for (width, height) in [(7, 7), (9, 7), (9, 9)] {
+                let board = generate_board(width, height, seed, 4);
+                for y in 0..height as usize {
+                    for x in 0..width as usize {
+                        let (mx, my) = (width as usize - 1 - x, height as usize - 1 - y);
+                        assert_eq!(board[y][x], board[y][mx], "seed {} {}x{} at {},{}", seed, width, height, x, y);
+                        assert_eq!(board[y][x], board[my][x], "seed {} {}x{} at {},{}", seed, width, height, x, y);
+                    }
+                }
+            }
+        }
+        assert_eq!(generate_board(9, 9, 5, 3), generate_board(9, 9, 5, 4));
+    }
+
+    #[test]
+    fn map_validation() {
+        assert!(validate_map_tiles(5, 5, &"P".repeat(25)).is_ok());
+        assert!(validate_map_tiles(5, 5, &"P".repeat(24)).is_err());
+        assert!(validate_map_tiles(4, 4, &"P".repeat(16)).is_err());
+        assert!(validate_map_tiles(5, 5, &"X".repeat(25)).is_err());
+        assert!(validate_map_tiles(5, 5, &format!("W{}", "P".repeat(24))).is_err());
+        assert!(validate_map_tiles(5, 5, &format!("{}W{}", "~".repeat(12), "R".repeat(12))).is_ok());
+        assert!(validate_map_objectives(5, 5, &".".repeat(25)).is_ok());
+        assert!(validate_map_objectives(5, 5, &"Z".repeat(25)).is_err());
+    }
+
+    #[test]
+    fn player_two_spawns_mirror_player_one() {
+        let p1 = spawn_positions(7, 7, 3, 0);
+        let p2 = spawn_positions(7, 7, 3, 1);
+        assert_eq!(p1, vec![(0, 0), (2, 0), (0, 2)]);
+        assert_eq!(p2, vec![(6, 6), (4, 6), (6, 4)]);
+        assert_eq!(spawn_positions(7, 7, 3, 2), vec![(6, 0), (4, 0), (6, 2)]);
+        assert_eq!(spawn_positions(7, 7, 3, 3), vec![(0, 6), (2, 6), (0, 4)]);
+    }
+}
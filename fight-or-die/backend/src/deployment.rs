@@ -0,0 +1,113 @@
+//! Army drafting before a game starts. Each player buys units with a point
+//! budget and places them in their corner; `lib.rs` keeps the armies hidden
+//! until everyone has locked in.
+
+use crate::board::spawn_positions;
+use crate::error::GameError;
+use crate::rules::{calculate_distance, get_class_stats};
+use crate::state::Seat;
+use crate::terrain::terrain_properties;
+use crate::{Position, TerrainType, UnitPlacement, SQUAD_CLASSES};
+
+/// How far from its corner a seat may place units (Manhattan distance)
+pub const DEPLOYMENT_ZONE_DEPTH: u8 = 2;
+
+/// Points each player gets to spend: the price of the default squad of this
+/// size, so the classic lineup is always affordable
+pub fn deployment_budget(squad_size: u8) -> u32 {
+    SQUAD_CLASSES.iter().cycle().take(squad_size as usize).map(|&class| get_class_stats(class).cost).sum()
+}
+
+/// Whether a tile is in a seat's deployment zone
+pub fn in_deployment_zone(width: u8, height: u8, seat: Seat, x: u8, y: u8) -> bool {
+    let (cx, cy) = spawn_positions(width, height, 1, seat)[0];
+    x < width && y < height && calculate_distance(x, y, cx, cy) <= DEPLOYMENT_ZONE_DEPTH
+}
+
+/// Check an army before it's locked in: at least one unit, within budget,
+/// every unit on a passable tile inside the seat's zone and no two on the
+/// same tile
+pub fn validate_deployment(
+    terrain: &[Vec<TerrainType>],
+    seat: Seat,
+    budget: u32,
+    units: &[UnitPlacement],
+) -> Result<(), GameError> {
+    let height = terrain.len() as u8;
+    let width = terrain.first().map_or(0, |row| row.len()) as u8;
+    if units.is_empty() {
+        return Err(GameError::EmptyDeployment);
+    }
+    let cost: u32 = units.iter().map(|u| get_class_stats(u.class).cost).sum();
+    if cost > budget {
+        return Err(GameError::OverBudget { cost, budget });
+    }
+    for (i, unit) in units.iter().enumerate() {
+        let Position { x, y } = unit.position;
+        if !in_deployment_zone(width, height, seat, x, y) {
+            return Err(GameError::OutsideDeploymentZone { x, y });
+        }
+        if !terrain_properties(terrain[y as usize][x as usize]).passable {
+            return Err(GameError::Impassable { x, y });
+        }
+        if units[..i].iter().any(|other| other.position == unit.position) {
+            return Err(GameError::Occupied { x, y });
+        }
+    }
+    Ok(())
+}
+
+/// The army a player gets when they don't pick one: the default squad on the
+/// seat's standard spawn tiles. Used for bots and when deployment times out.
+pub fn default_deployment(width: u8, height: u8, squad_size: u8, seat: Seat) -> Vec<UnitPlacement> {
+    spawn_positions(width, height, squad_size, seat)
+        .into_iter()
+        .zip(SQUAD_CLASSES.iter().cycle())
+        .map(|((x, y), &class)| UnitPlacement { class, position: Position { x, y } })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{UnitClass, MAX_SQUAD_SIZE, MIN_SQUAD_SIZE};
+
+    fn plains(size: usize) -> Vec<Vec<TerrainType>> {
+        vec![vec![TerrainType::Plains; size]; size]
+    }
+
+    fn placed(class: UnitClass, x: u8, y: u8) -> UnitPlacement {
+        UnitPlacement { class, position: Position { x, y } }
+    }
+
+    #[test]
+    fn default_squads_fit_their_budget_and_zone() {
+        assert_eq!(deployment_budget(3), 9);
+        for squad_size in MIN_SQUAD_SIZE..=MAX_SQUAD_SIZE {
+            for seat in 0..4 {
+                let army = default_deployment(7, 7, squad_size, seat);
+                assert_eq!(army.len(), squad_size as usize);
+                assert_eq!(validate_deployment(&plains(7), seat, deployment_budget(squad_size), &army), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn bad_armies_are_rejected() {
+        let budget = deployment_budget(3);
+        let mut terrain = plains(5);
+        assert_eq!(validate_deployment(&terrain, 0, budget, &[]), Err(GameError::EmptyDeployment));
+
+        let tanks = [placed(UnitClass::Tank, 0, 0), placed(UnitClass::Tank, 1, 0), placed(UnitClass::Tank, 0, 1)];
+        assert_eq!(validate_deployment(&terrain, 0, budget, &tanks), Err(GameError::OverBudget { cost: 12, budget }));
+
+        let scouts = [placed(UnitClass::Scout, 4, 4), placed(UnitClass::Scout, 2, 2)];
+        assert_eq!(validate_deployment(&terrain, 1, budget, &scouts), Err(GameError::OutsideDeploymentZone { x: 2, y: 2 }));
+
+        let stacked = [placed(UnitClass::Scout, 4, 4), placed(UnitClass::Scout, 4, 4)];
+        assert_eq!(validate_deployment(&terrain, 1, budget, &stacked), Err(GameError::Occupied { x: 4, y: 4 }));
+
+        terrain[4][4] = TerrainType::Water;
+        assert_eq!(validate_deployment(&terrain, 1, budget, &stacked), Err(GameError::Impassable { x: 4, y: 4 }));
+    }
+}
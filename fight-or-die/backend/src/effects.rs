@@ -0,0 +1,102 @@
+//! Status effects on units: what each kind does and how they wear off.
+//! Effects ride along on `UnitState.effects`; `GameState` consults them when
+//! it works out damage and movement, and ticks them as turns change hands.
+
+use crate::{ActiveEffect, EffectKind, EffectSource, TerrainType};
+
+/// Owner's turns a Poison ability keeps hurting for
+pub const POISON_TURNS: u8 = 3;
+
+/// Movement points lost by a unit starting its turn in a Swamp
+pub const SWAMP_SLOW: i32 = 2;
+
+/// What a unit's effects do to it for the turn that is starting
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TurnStart {
+    pub poison_damage: i32,
+    pub movement_penalty: i32,
+    pub stunned: bool,
+}
+
+/// Combined magnitude of every effect of one kind
+pub fn total(effects: &[ActiveEffect], kind: EffectKind) -> i32 {
+    effects.iter().filter(|e| e.kind == kind).map(|e| e.magnitude).sum()
+}
+
+/// Whether any effect of this kind is on the unit
+pub fn has_effect(effects: &[ActiveEffect], kind: EffectKind) -> bool {
+    effects.iter().any(|e| e.kind == kind)
+}
+
+/// Put an effect on a unit. The same kind from the same source is refreshed
+/// rather than stacked, so using an ability twice doesn't double it.
+pub fn add_effect(effects: &mut Vec<ActiveEffect>, effect: ActiveEffect) {
+    effects.retain(|e| !(e.kind == effect.kind && e.source == effect.source));
+    effects.push(effect);
+}
+
+/// Effect a tile puts on a unit that starts its owner's turn on it
+pub fn terrain_effect(terrain: TerrainType) -> Option<ActiveEffect> {
+    match terrain {
+        TerrainType::Swamp => Some(ActiveEffect {
+            kind: EffectKind::Slowed,
+            magnitude: SWAMP_SLOW,
+            turns_left: 1,
+            source: EffectSource::Terrain(terrain),
+        }),
+        _ => None,
+    }
+}
+
+/// Tick a unit's effects as its owner's turn starts: effects with no turns
+/// left wear off, the rest count down one turn and take hold for this one
+pub fn start_turn(effects: &mut Vec<ActiveEffect>) -> TurnStart {
+    effects.retain(|e| e.turns_left > 0);
+    for effect in effects.iter_mut() {
+        effect.turns_left -= 1;
+    }
+    TurnStart {
+        poison_damage: total(effects, EffectKind::Poison),
+        movement_penalty: total(effects, EffectKind::Slowed),
+        stunned: has_effect(effects, EffectKind::Stun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(kind: EffectKind, magnitude: i32, turns_left: u8, ability: &str) -> ActiveEffect {
+        ActiveEffect { kind, magnitude, turns_left, source: EffectSource::Ability(ability.to_string()) }
+    }
+
+    #[test]
+    fn effects_last_into_their_owners_turns() {
+        let mut effects = vec![
+            effect(EffectKind::Poison, 10, 2, "poison_dart"),
+            effect(EffectKind::Stun, 0, 1, "concussion"),
+            effect(EffectKind::Fortified, 1, 0, "fortify"),
+        ];
+        let start = start_turn(&mut effects);
+        assert_eq!(start, TurnStart { poison_damage: 10, movement_penalty: 0, stunned: true });
+        assert!(!has_effect(&effects, EffectKind::Fortified));
+
+        assert_eq!(start_turn(&mut effects), TurnStart { poison_damage: 10, ..TurnStart::default() });
+        assert_eq!(start_turn(&mut effects), TurnStart::default());
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn same_source_refreshes_instead_of_stacking() {
+        let mut effects = vec![effect(EffectKind::Poison, 10, 1, "poison_dart")];
+        add_effect(&mut effects, effect(EffectKind::Poison, 10, 3, "poison_dart"));
+        add_effect(&mut effects, effect(EffectKind::Poison, 5, 3, "venom"));
+        assert_eq!(effects.len(), 2);
+        assert_eq!(total(&effects, EffectKind::Poison), 15);
+        assert_eq!(effects[0].turns_left, 3);
+
+        let swamp = terrain_effect(TerrainType::Swamp).unwrap();
+        assert_eq!((swamp.kind, swamp.magnitude), (EffectKind::Slowed, SWAMP_SLOW));
+        assert_eq!(terrain_effect(TerrainType::Plains), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! Errors returned by reducers. A reducer that returns `Err` rolls back and
+//! the error's message reaches the caller's reducer-event callback.
+
+use crate::sealing::VIEW_KEY_LENGTH;
+use crate::{
+    MAX_PLAYERS, MAX_SQUAD_SIZE, MAX_TURN_TIME_SECS, MIN_FREE_FOR_ALL_PLAYERS, MIN_SQUAD_SIZE, MIN_TURN_TIME_SECS,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameError {
+    // Players and games
+    PlayerNotFound,
+    PlayerAlreadyExists,
+    InvalidViewKey(usize),
+    GameNotFound(u32),
+    NotInGame,
+    AlreadyInGame,
+    GameNotJoinable,
+    GameNotInProgress,
+    GameNotFinished,
+    SeatOutOfOrder(usize),
+    AlreadyQueued,
+    NotQueued,
+    NotHost,
+    LobbyHasPlayers,
+    InviteRequired,
+    InvalidInviteCode,
+    GameNotPrivate,
+    InviteCodeTooShort(usize),
+    RematchAlreadyRequested,
+    DuelOnly,
+    PlayerCannotSpectate,
+    AlreadyWatching,
+    NotWatching,
+    NotYourTurn,
+    AlreadyEliminated,
+
+    // Tournaments
+    TournamentNotFound(u32),
+    RegistrationClosed,
+    AlreadyRegistered,
+    NotRegistered,
+    NotOrganizer,
+    NotEnoughPlayers(usize),
+
+    // Deployment
+    NotDeploying,
+    AlreadyDeployed,
+    EmptyDeployment,
+    OverBudget { cost: u32, budget: u32 },
+    OutsideDeploymentZone { x: u8, y: u8 },
+
+    // Units
+    UnitNotFound(u32),
+    NotOwner(u32),
+    UnitDead(u32),
+    AlreadyAttacked(u32),
+    Stunned(u32),
+
+    // Abilities
+    AbilityNotFound(String),
+    AbilityNotForClass(String),
+    AbilityCoolingDown { turns: u8 },
+    TargetRequired,
+    InvalidTarget(u32),
+
+    // Movement
+    OutOfBounds { x: u8, y: u8 },
+    AlreadyThere { x: u8, y: u8 },
+    Occupied { x: u8, y: u8 },
+    Impassable { x: u8, y: u8 },
+    NoPath { x: u8, y: u8, movement: u8 },
+
+    // Combat
+    TargetDead(u32),
+    FriendlyFire(u32),
+    OutOfRange { range: u8 },
+    LineOfFireBlocked { x: u8, y: u8 },
+
+    // Setup
+    InvalidSquadSize(u8),
+    InvalidTurnTime(u32),
+    InvalidPlayerCount(u8),
+    SpectatorDelayTooShort(u32),
+    MapNotFound(String),
+    MapPresetExists(String),
+    InvalidMap(String),
+    InvalidAbility(String),
+    NotAdmin,
+
+    /// A logged action doesn't match what the rules produce on replay
+    Diverged(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::PlayerNotFound => write!(f, "Player not found"),
+            GameError::PlayerAlreadyExists => write!(f, "Player already exists"),
+            GameError::InvalidViewKey(len) => write!(f, "View key must be {} bytes, got {}", VIEW_KEY_LENGTH, len),
+            GameError::GameNotFound(id) => write!(f, "Game not found: {}", id),
+            GameError::NotInGame => write!(f, "Player not in game"),
+            GameError::AlreadyInGame => write!(f, "Player already in game"),
+            GameError::GameNotJoinable => write!(f, "Game not accepting players"),
+            GameError::GameNotInProgress => write!(f, "Game not in progress"),
+            GameError::GameNotFinished => write!(f, "Game not finished"),
+            GameError::SeatOutOfOrder(seat) => write!(f, "Seat {} joined out of order", seat),
+            GameError::AlreadyQueued => write!(f, "Already in the matchmaking queue"),
+            GameError::NotQueued => write!(f, "Not in the matchmaking queue"),
+            GameError::NotHost => write!(f, "Only the host can change the lobby"),
+            GameError::LobbyHasPlayers => write!(f, "Lobby can't change once other players have joined"),
+            GameError::InviteRequired => write!(f, "Game is private - join with its invite code"),
+            GameError::InvalidInviteCode => write!(f, "Wrong invite code"),
+            GameError::GameNotPrivate => write!(f, "Game is public"),
+            GameError::InviteCodeTooShort(min) => write!(f, "Invite code must be at least {} characters", min),
+            GameError::RematchAlreadyRequested => write!(f, "Rematch already requested"),
+            GameError::DuelOnly => write!(f, "Only available for duels"),
+            GameError::PlayerCannotSpectate => write!(f, "Players can't spectate their own game"),
+            GameError::AlreadyWatching => write!(f, "Already watching this game"),
+            GameError::NotWatching => write!(f, "Not watching this game"),
+            GameError::NotYourTurn => write!(f, "Not your turn"),
+            GameError::AlreadyEliminated => write!(f, "Player already eliminated"),
+            GameError::TournamentNotFound(id) => write!(f, "Tournament not found: {}", id),
+            GameError::RegistrationClosed => write!(f, "Tournament registration is closed"),
+            GameError::AlreadyRegistered => write!(f, "Already registered for this tournament"),
+            GameError::NotRegistered => write!(f, "Not registered for this tournament"),
+            GameError::NotOrganizer => write!(f, "Only the organizer can start the tournament"),
+            GameError::NotEnoughPlayers(min) => write!(f, "Tournament needs at least {} players", min),
+            GameError::NotDeploying => write!(f, "Game is not in its deployment phase"),
+            GameError::AlreadyDeployed => write!(f, "Army already locked in"),
+            GameError::EmptyDeployment => write!(f, "Army needs at least one unit"),
+            GameError::OverBudget { cost, budget } => write!(f, "Army costs {} points, budget is {}", cost, budget),
+            GameError::OutsideDeploymentZone { x, y } => write!(f, "Outside your deployment zone: {},{}", x, y),
+            GameError::UnitNotFound(id) => write!(f, "Unit not found: {}", id),
+            GameError::NotOwner(id) => write!(f, "Unit not owned by player: {}", id),
+            GameError::UnitDead(id) => write!(f, "Unit is dead: {}", id),
+            GameError::AlreadyAttacked(id) => write!(f, "Unit already attacked: {}", id),
+            GameError::Stunned(id) => write!(f, "Unit is stunned: {}", id),
+            GameError::AbilityNotFound(name) => write!(f, "Ability not found: {}", name),
+            GameError::AbilityNotForClass(name) => write!(f, "Unit class can't use ability: {}", name),
+            GameError::AbilityCoolingDown { turns } => write!(f, "Ability ready in {} turn(s)", turns),
+            GameError::TargetRequired => write!(f, "Ability needs a target"),
+            GameError::InvalidTarget(id) => write!(f, "Invalid target for ability: {}", id),
+            GameError::OutOfBounds { x, y } => write!(f, "Invalid position: {},{}", x, y),
+            GameError::AlreadyThere { x, y } => write!(f, "Unit already at {},{}", x, y),
+            GameError::Occupied { x, y } => write!(f, "Destination occupied: {},{}", x, y),
+            GameError::Impassable { x, y } => write!(f, "Impassable terrain: {},{}", x, y),
+            GameError::NoPath { x, y, movement } => write!(f, "No path to {},{} within {} movement", x, y, movement),
+            GameError::TargetDead(id) => write!(f, "Target already dead: {}", id),
+            GameError::FriendlyFire(id) => write!(f, "Cannot attack own or allied unit: {}", id),
+            GameError::OutOfRange { range } => write!(f, "Target out of range (range {})", range),
+            GameError::LineOfFireBlocked { x, y } => write!(f, "Line of fire blocked at {},{}", x, y),
+            GameError::InvalidSquadSize(size) => {
+                write!(f, "Invalid squad size: {} (must be {}-{})", size, MIN_SQUAD_SIZE, MAX_SQUAD_SIZE)
+            }
+            GameError::InvalidTurnTime(secs) => {
+                write!(f, "Invalid turn time: {}s (must be {}-{}s)", secs, MIN_TURN_TIME_SECS, MAX_TURN_TIME_SECS)
+            }
+            GameError::InvalidPlayerCount(players) => {
+                write!(f, "Invalid player count: {} (must be {}-{})", players, MIN_FREE_FOR_ALL_PLAYERS, MAX_PLAYERS)
+            }
+            GameError::SpectatorDelayTooShort(min) => {
+                write!(f, "Spectator delay must be at least {} turns, one per seat", min)
+            }
+            GameError::MapNotFound(name) => write!(f, "Map not found: {}", name),
+            GameError::MapPresetExists(name) => write!(f, "Map preset already exists: {}", name),
+            GameError::InvalidMap(reason) => write!(f, "Invalid map: {}", reason),
+            GameError::InvalidAbility(reason) => write!(f, "Invalid ability: {}", reason),
+            GameError::NotAdmin => write!(f, "Admins only"),
+            GameError::Diverged(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
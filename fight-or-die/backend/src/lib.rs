@@ -1,19 +1,398 @@
-use spacetimedb::{spacetimedb, Identity, ReducerContext};
+// The table derive of spacetimedb 0.10 emits an `AssertSpacetimeFields` trait
+// per table that current compilers report as never used
+#![allow(dead_code)]
+
+use spacetimedb::sats::bsatn;
+use spacetimedb::sats::hash::hash_bytes;
+use spacetimedb::{spacetimedb, Address, Identity, ReducerContext, SpacetimeType, Timestamp};
+
+mod abilities;
+mod ai;
+mod board;
+mod deployment;
+mod effects;
+mod error;
+mod matchmaking;
+mod progression;
+mod rules;
+mod sealing;
+mod state;
+mod terrain;
+mod tournament;
+
+use abilities::{default_abilities, validate_ability};
+use ai::{next_action, BotAction};
+use board::{
+    classic_board, default_objectives, deserialize_board, deserialize_objectives, generate_board, serialize_board,
+    serialize_objectives, tiles_to_board, validate_map_objectives, validate_map_tiles,
+};
+use deployment::{default_deployment, deployment_budget, validate_deployment};
+pub use error::GameError;
+use matchmaking::{closest_match, elo_change, STARTING_RATING};
+use progression::{earned_achievements, level_for_xp, summarize_game, xp_for_game, GameSummary};
+use rules::get_class_stats;
+use sealing::{pad, seal, VIEW_KEY_LENGTH};
+use state::{seat_teams, team_count, CaptureState, GameState, Seat, Team, UnitState};
+use tournament::{
+    elimination_pairings, round_count, seed_by_rating, swiss_pairings, swiss_ranking, Standing, DRAW_POINTS,
+    MIN_TOURNAMENT_PLAYERS, WIN_POINTS,
+};
+
+// ===== CONSTANTS =====
+
+/// Squad size used when a client doesn't ask for one
+pub const DEFAULT_SQUAD_SIZE: u8 = 3;
+/// Smallest squad a game can be created with
+pub const MIN_SQUAD_SIZE: u8 = 3;
+/// Largest squad that still fits in a corner of the 5x5 board
+pub const MAX_SQUAD_SIZE: u8 = 5;
+
+/// Smallest board edge - spawn slots need a 5x5 board to stay apart
+pub const MIN_BOARD_SIZE: u8 = 5;
+/// Largest board edge accepted for map presets
+pub const MAX_BOARD_SIZE: u8 = 15;
+/// Smallest board edge for games of more than two players, so squads in
+/// neighbouring corners don't overlap
+pub const MIN_MULTIPLAYER_BOARD_SIZE: u8 = 7;
+
+/// Fewest players a free-for-all can be created for
+pub const MIN_FREE_FOR_ALL_PLAYERS: u8 = 3;
+/// Most players a game can seat - one per corner
+pub const MAX_PLAYERS: u8 = 4;
+
+/// Turn timer used when a lobby doesn't pick one, in seconds
+const DEFAULT_TURN_TIME_SECS: u32 = 90;
+/// Shortest turn timer a lobby can pick
+const MIN_TURN_TIME_SECS: u32 = 15;
+/// Longest turn timer a lobby can pick
+const MAX_TURN_TIME_SECS: u32 = 600;
+/// Turns in a row a player can let run out before forfeiting the game
+const MAX_CONSECUTIVE_TIMEOUTS: u8 = 3;
+
+/// Map used for games paired by matchmaking
+const MATCHMAKING_MAP: MapChoice = MapChoice::Small;
+/// How long a queued player waits before `run_bots` pairs them with a bot
+const BOT_FILL_WAIT_SECS: u64 = 30;
+/// Difficulty of bots filling in for a missing opponent
+const BOT_FILL_DIFFICULTY: BotDifficulty = BotDifficulty::Normal;
+
+/// Shortest invite code a host can set, so codes can't be guessed by trying them
+const MIN_INVITE_CODE_LENGTH: usize = 8;
+
+/// Default squad composition, cycled through for squads nobody picked. A
+/// squad of this kind also sets the deployment budget for its size.
+const SQUAD_CLASSES: [UnitClass; 3] = [UnitClass::Infantry, UnitClass::Tank, UnitClass::Scout];
 
 // ===== ENUMS =====
 
-/// Terrain types that affect combat defense values
+/// Terrain types. What each one does lives in `terrain::terrain_properties`.
 #[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
 pub enum TerrainType {
-    Plains,    // No defense bonus
-    Forest,    // -10 damage taken
-    Mountain,  // -20 damage taken
+    Plains,    // Open ground
+    Forest,    // Cover that blocks sight
+    Mountain,  // High ground: best cover, wide view, slow to climb
+    Swamp,     // Slows units that start their turn in it
+    Water,     // Impassable
+    River,     // Slow to cross and exposed while crossing
+    Road,      // Cheap to move along
+    Ruins,     // Cover on slightly raised ground
+}
+
+/// Objectives placed on top of terrain
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum ObjectiveType {
+    None,
+    CapturePoint,   // Awards victory points to its holder every turn
+    HealingStation, // Restores HP to the unit standing on it
+}
+
+/// Unit classes - stats live in `get_class_stats`
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum UnitClass {
+    Infantry,  // Balanced all-rounder
+    Tank,      // Slow but strong
+    Scout,     // Fast but weak
+    Archer,    // Shoots from range 3
+    Artillery, // Slow and fragile, shoots from range 4
+}
+
+/// Facing of a unit on the board. North is towards row 0.
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+/// Map selection when creating a game
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub enum MapChoice {
+    Small,          // 5x5
+    Medium,         // 7x7
+    Large,          // 9x9
+    Preset(String), // Name of a MapPreset row
+}
+
+/// Achievements a player can unlock. Rules live in `earned_achievements`.
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum AchievementKind {
+    FirstVictory, // Win a game
+    Flawless,     // Win without any of your units taking damage, other than by surrender
+    OpenGround,   // Win with a final blow struck from a Plains tile
+    Conqueror,    // Win on victory points
+    Veteran,      // Finish 10 games
+}
+
+/// Attacks a player has made from one terrain type
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub struct TerrainCount {
+    pub terrain: TerrainType,
+    pub attacks: u32,
+}
+
+/// What an ability does. `AbilitySpec.power` says how much.
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum AbilityKind {
+    Shot,    // Hit an enemy in range for `power`% of the unit's damage, with no counter-attack
+    Heal,    // Restore `power` HP to another friendly unit in range
+    Fortify, // +`power` defense until the unit's next turn
+    Dash,    // +`power` movement points this turn
+    Poison,  // Poison an enemy in range for `power` HP a turn (see `effects::POISON_TURNS`)
+    Stun,    // Stun an enemy in range for `power` of its turns
+}
+
+/// Kinds of status effect. `ActiveEffect.magnitude` says how strong.
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum EffectKind {
+    Poison,    // Loses `magnitude` HP at the start of each of the owner's turns, down to 1
+    Stun,      // Can't move, attack, strike back or use abilities
+    Fortified, // +`magnitude` defense
+    Slowed,    // -`magnitude` movement points
+}
+
+/// What put a status effect on a unit
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub enum EffectSource {
+    Ability(String), // Name of the ability used
+    Terrain(TerrainType),
+}
+
+/// How well a bot plays. See `ai::next_action`.
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum BotDifficulty {
+    Easy,   // Attacks whatever is in reach and walks straight at the enemy
+    Normal, // Focuses weak targets and prefers Forest/Mountain tiles
+    Hard,   // Picks the best trades and pulls hurt units back to Mountains
+}
+
+/// How a tournament is played
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum TournamentFormat {
+    SingleElimination, // Losers are out; drawn games go to the better seed
+    Swiss,             // Everyone plays every round against players on similar scores
+}
+
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum TournamentStatus {
+    Registration,
+    InProgress,
+    Finished,
+}
+
+/// Who can join a waiting game
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub enum LobbyVisibility {
+    Public,                     // Anyone with the game id
+    Private(Option<Identity>),  // Invite code holders, plus the invited player if any
+}
+
+/// Who plays whom. Seats take turns in order.
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum GameMode {
+    Duel,           // Two players, one against one
+    FreeForAll(u8), // 3-4 players, each on their own
+    Teams,          // 2v2: seats 1 and 3 against seats 2 and 4
+}
+
+/// Options for a game, chosen by its creator. Can be changed with
+/// `update_lobby` until another player joins.
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct LobbySettings {
+    pub squad_size: Option<u8>,      // Default 3
+    pub map: MapChoice,
+    pub mode: GameMode,              // Modes for more than two need a Medium or larger map
+    pub seed: Option<u64>,           // Random unless given
+    pub turn_time_secs: Option<u32>, // Default 90
+    pub visibility: LobbyVisibility,
+    pub spectator_delay: Option<u32>, // Turns spectators trail the game, default and minimum one per seat
+}
+
+/// What a spectator is shown
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub enum SpectatorPerspective {
+    Full,             // Every unit
+    Player(Identity), // Only what this player could see
+}
+
+/// An ability's balance numbers, as defined in `AbilityDefinition`. Every use
+/// logs a copy so replays don't change when abilities are rebalanced.
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct AbilitySpec {
+    pub name: String,
+    pub kind: AbilityKind,
+    pub range: u8,    // Manhattan distance to the target; unused by Fortify and Dash
+    pub power: i32,
+    pub cooldown: u8, // Owner's turns before it can be used again
+}
+
+/// An ability a unit has used recently
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct AbilityCooldown {
+    pub ability: String,
+    pub turns_left: u8, // Counts down at the start of each of the owner's turns
+}
+
+/// A status effect on a unit
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct ActiveEffect {
+    pub kind: EffectKind,
+    pub magnitude: i32,
+    pub turns_left: u8, // Owner's turns still to come that it lasts into
+    pub source: EffectSource,
+}
+
+/// A tile on the board
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: u8,
+    pub y: u8,
+}
+
+/// A unit as it entered the game, so a replay knows the starting roster
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct SpawnedUnit {
+    pub unit_id: u32,
+    pub class: UnitClass,
+    pub position: Position,
+    pub facing: Direction,
+}
+
+/// A unit a player buys and places during deployment
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
+pub struct UnitPlacement {
+    pub class: UnitClass,
+    pub position: Position,
+}
+
+/// A unit as seen by one player
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct VisibleUnit {
+    pub unit_id: u32,
+    pub owner: Identity,
+    pub class: UnitClass,
+    pub x: u8,
+    pub y: u8,
+    pub hp: i32,
+    pub movement_left: u8,
+    pub has_attacked: bool,
+    pub facing: Direction,
+    pub effects: Vec<ActiveEffect>,
+    pub cooldowns: Vec<AbilityCooldown>, // Only filled in for the viewer's own units
+}
+
+/// Expected outcome of an attack, worked out by `preview_attack`
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct AttackPreview {
+    pub attacker_id: u32,
+    pub target_id: u32,
+    pub damage: i32,
+    pub flanking: bool,
+    pub counter_damage: i32, // 0 if the target dies or can't reach the attacker
+    pub range: u8,
+    pub obstruction: Option<Position>, // Cover the shot would pass over, if any
+    pub target_hp_after: i32,
+    pub attacker_hp_after: i32,
+}
+
+/// Everything one player can see of a game: the tiles in their team's sight,
+/// their team's units, other units standing on those tiles and their latest
+/// attack preview. Sent to the player sealed in a `SealedView`.
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct PlayerView {
+    pub game_id: u32,
+    pub turn_number: u32,
+    pub tiles: Vec<Position>,
+    pub units: Vec<VisibleUnit>,
+    pub preview: Option<AttackPreview>,
+}
+
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct MoveAction {
+    pub unit_id: u32,
+    pub from: Position,
+    pub to: Position,             // Where the unit ended up
+    pub path: Vec<Position>,      // Tiles stepped on, excluding `from`
+    pub cost: u8,                 // Movement points spent; a bump spends them all
+    pub destination: Position,    // Tile asked for; `to` falls short of it after a bump
+    pub bumped: Option<Position>, // Unseen unit the move ran into, if any
+}
+
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct AttackAction {
+    pub attacker_id: u32,
+    pub target_id: u32,
+    pub damage: i32,
+    pub flanking: bool,
+    pub counter_damage: i32,
+    pub range: u8,                     // Distance of the shot
+    pub obstruction: Option<Position>, // Cover the shot passed over, if any
+}
+
+/// A unit used an ability. `amount` is the damage dealt, HP healed,
+/// defense gained or movement gained.
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct AbilityAction {
+    pub unit_id: u32,
+    pub target_id: Option<u32>, // None for abilities a unit uses on itself
+    pub ability: AbilitySpec,
+    pub amount: i32,
+}
+
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct JoinAction {
+    pub units: Vec<SpawnedUnit>,
+}
+
+/// A unit's state at one point of a replay
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub struct UnitSnapshot {
+    pub unit_id: u32,
+    pub owner: Identity,
+    pub class: UnitClass,
+    pub position: Position,
+    pub hp: i32,
+    pub facing: Direction,
+}
+
+/// What happened in one `GameAction`
+#[derive(SpacetimeType, Debug, Clone, PartialEq)]
+pub enum ActionPayload {
+    Join(JoinAction), // Creating a game counts as player 1 joining it
+    Move(MoveAction),
+    Attack(AttackAction),
+    Ability(AbilityAction),
+    EndTurn,
+    Surrender,
+    Timeout, // Turn ended by the server after the deadline passed
+    Forfeit, // Game given up by the server after too many timeouts
 }
 
 /// Game state tracking
 #[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
 pub enum GameStatus {
     WaitingForPlayers,
+    Deployment, // Every seat taken; players are placing their armies
     InProgress,
     Finished,
 }
@@ -23,492 +402,2873 @@ pub enum GameStatus {
 /// Player information stored in the database
 #[spacetimedb(table)]
 pub struct Player {
-    #[primary_key]
+    #[primarykey]
     pub id: Identity,
     pub username: String,
+    pub rating: i32,               // Elo, updated when a game finishes
+    pub current_game: Option<u32>, // Latest unfinished game the player is in
+    pub xp: u32,
+    pub level: u32,                // Follows from xp, starts at 1
+    pub created_at: u64,
+}
+
+/// Career totals of a player, added to whenever one of their games finishes
+#[spacetimedb(table)]
+pub struct PlayerStats {
+    #[primarykey]
+    pub player: Identity,
+    pub games_played: u32,
+    pub games_won: u32,
+    pub games_drawn: u32,
+    pub damage_dealt: u64,
+    pub damage_taken: u64,
+    pub units_killed: u32,
+    pub units_lost: u32,
+    pub attacks_by_terrain: Vec<TerrainCount>,
+    pub favourite_terrain: Option<TerrainType>, // Terrain attacked from most often
+}
+
+/// An achievement a player has unlocked, and the game that unlocked it
+#[spacetimedb(table)]
+pub struct PlayerAchievement {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub player: Identity,
+    pub achievement: AchievementKind,
+    pub game_id: u32,
+    pub unlocked_at: u64,
+}
+
+/// Invite code of a private game, kept only as a hash. Private; the host
+/// picks the code with `set_invite_code` and shares it themselves.
+#[spacetimedb(table(private))]
+pub struct GameInvite {
+    #[primarykey]
+    pub game_id: u32,
+    pub code_hash: Vec<u8>, // See `invite_code_hash`
+    pub host: Identity,
+}
+
+/// A player who gave the right invite code for a private game and may now
+/// `join_game` it. Private, like `GameInvite`.
+#[spacetimedb(table(private))]
+pub struct InvitePass {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub player: Identity,
+}
+
+/// A player asking for a rematch of a finished game, waiting on the opponent
+#[spacetimedb(table)]
+pub struct RematchRequest {
+    #[primarykey]
+    pub game_id: u32,
+    pub requested_by: Identity,
+    pub created_at: u64,
+}
+
+/// Someone watching a game they aren't playing in
+#[spacetimedb(table)]
+pub struct Spectator {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub spectator: Identity,
+    pub perspective: SpectatorPerspective,
+    pub joined_at: u64,
+}
+
+/// Units as spectators see them, `Game.spectator_delay` turns behind the
+/// game. One set of rows per perspective in use.
+#[spacetimedb(table)]
+pub struct SpectatorUnit {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub perspective: SpectatorPerspective,
+    pub unit_id: u32,
+    pub owner: Identity,
+    pub class: UnitClass,
+    pub x: u8,
+    pub y: u8,
+    pub hp: i32,
+    pub facing: Direction,
+    pub as_of_turn: u32, // Turn the view shows the start of
+}
+
+/// `GameAction` rows old enough for spectators to see
+#[spacetimedb(table)]
+pub struct SpectatorAction {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub sequence: u32,
+    pub turn_number: u32,
+    pub player: Identity,
+    pub payload: ActionPayload,
+}
+
+/// The state spectators are shown, kept between refreshes so each one only
+/// replays the actions that have become old enough since the last. Private,
+/// like the log it is built from.
+#[spacetimedb(table(private))]
+pub struct SpectatorReplay {
+    #[primarykey]
+    pub game_id: u32,
+    pub next_sequence: u32, // First logged action not replayed yet
+    pub units: Vec<VisibleUnit>, // Every unit, cooldowns included
+    pub capture_owners: Vec<Option<u8>>, // Seat holding each capture point, in board order
+    pub players_joined: u8,
+    pub current_seat: u8,
+    pub turn_number: u32,
+    pub round: u32,
+    pub team_points: Vec<u32>,
+    pub status: GameStatus,
+    pub winning_team: Option<u8>,
+}
+
+/// A tournament and the settings every one of its games is played with
+#[spacetimedb(table)]
+pub struct Tournament {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub name: String,
+    pub organizer: Identity,
+    pub format: TournamentFormat,
+    pub settings: LobbySettings,
+    pub status: TournamentStatus,
+    pub current_round: u32,  // 0 until the tournament starts
+    pub total_rounds: u32,   // Set when the tournament starts
+    pub winner: Option<Identity>,
     pub created_at: u64,
 }
 
-/// Game session tracking - manages matches between two players
+/// A player registered for a tournament
+#[spacetimedb(table)]
+pub struct TournamentParticipant {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub tournament_id: u32,
+    pub player: Identity,
+    pub seed: u32,     // 0 is the top seed; assigned by rating when the tournament starts
+    pub score: u32,    // 2 per win or bye, 1 per draw
+    pub eliminated: bool,
+    pub had_bye: bool,
+    pub registered_at: u64,
+}
+
+/// A round of a tournament. The next one starts when all its matches are finished.
+#[spacetimedb(table)]
+pub struct TournamentRound {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub tournament_id: u32,
+    pub number: u32, // Starts at 1
+    pub finished: bool,
+    pub started_at: u64,
+}
+
+/// One pairing in a tournament round and the game it is played in
+#[spacetimedb(table)]
+pub struct TournamentMatch {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub tournament_id: u32,
+    pub round: u32,
+    pub slot: u32,                 // Position in the round; in a bracket, slots 2k and 2k+1 feed the next round's slot k
+    pub player1: Identity,
+    pub player2: Option<Identity>, // None is a bye
+    pub game_id: Option<u32>,      // None for byes
+    pub winner: Option<Identity>,  // None with `finished` set is a draw
+    pub finished: bool,
+}
+
+/// Players waiting for `enqueue` to pair them with an opponent
+#[spacetimedb(table)]
+pub struct MatchmakingQueue {
+    #[primarykey]
+    pub player: Identity,
+    pub rating: i32,
+    pub joined_at: u64,
+}
+
+/// A bot seated in a game. `run_bots` plays its turns; bots have no
+/// `Player` row, so their games are unrated.
+#[spacetimedb(table)]
+pub struct GameBot {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub bot: Identity,
+    pub difficulty: BotDifficulty,
+}
+
+/// Balance data for unit abilities. Admins change rows with `set_ability`
+/// and the next use picks them up, without republishing the module.
+#[spacetimedb(table)]
+pub struct AbilityDefinition {
+    #[primarykey]
+    pub name: String,
+    pub class: UnitClass, // Class that has the ability
+    pub kind: AbilityKind,
+    pub range: u8,
+    pub power: i32,
+    pub cooldown: u8,
+    pub enabled: bool,
+    pub updated_at: u64,
+}
+
+/// Identities allowed to change game data. The module owner is added on init.
+#[spacetimedb(table)]
+pub struct Admin {
+    #[primarykey]
+    pub identity: Identity,
+    pub added_at: u64,
+}
+
+/// The module's own address, recorded on init. Scheduled reducers run under
+/// it, which is how they tell the schedule apart from a client calling them.
+#[spacetimedb(table(private))]
+pub struct ModuleAddress {
+    #[primarykey]
+    pub id: u32, // Always 0
+    pub address: Address,
+}
+
+/// Game session tracking - manages matches between two to four players.
+/// Everyone seated is in `GameParticipant`; the first two seats are also
+/// here for two-player clients.
 #[spacetimedb(table)]
 pub struct Game {
-    #[primary_key]
+    #[primarykey]
     #[autoinc]
     pub id: u32,
     pub player1: Identity,
     pub player2: Option<Identity>,
+    pub mode: GameMode,
     pub current_turn: Identity,
     pub status: GameStatus,
-    pub winner: Option<Identity>,
+    pub winner: Option<Identity>, // Set when the winning team is a single player
+    pub winning_team: Option<u8>,
+    pub squad_size: u8, // Size of the default squad; sets the deployment budget
+    pub turn_number: u32, // Starts at 1, +1 on every end_turn
+    pub round: u32, // Starts at 1, +1 each time the turn order starts over
+    pub team_points: Vec<u32>, // Victory points per team
+    pub turn_time_limit: u64, // Milliseconds each turn gets
+    pub turn_deadline: u64, // When the current turn times out; 0 until the game starts
+    pub private: bool, // Joinable only through `GameInvite`
+    pub invited: Option<Identity>, // Player who can join a private game without the code
+    pub rematch_of: Option<u32>,
+    pub spectator_delay: u32, // Turns spectators trail the game, at least one per seat
     pub created_at: u64,
 }
 
-/// 5x5 game board with terrain data
+/// A player's seat in a game. Seats take turns in order; teams are
+/// numbered from 0 as in `state::seat_teams`.
+#[spacetimedb(table)]
+pub struct GameParticipant {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub seat: u8,
+    pub player: Identity,
+    pub team: u8,
+    pub timeouts: u8, // Turns in a row that ran out on the player
+    pub eliminated: bool, // All units lost; the game goes on without them
+    pub deployed: bool, // Army locked in for the deployment phase
+    pub joined_at: u64,
+}
+
+/// A locked-in army waiting for the rest of the players. Private, so nobody
+/// sees another player's army before the game starts; the rows are turned
+/// into units and removed once every seat has locked in.
+#[spacetimedb(table(private))]
+pub struct PendingDeployment {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub player: Identity,
+    pub units: Vec<UnitPlacement>,
+    pub locked_at: u64,
+}
+
+/// Game board with terrain data
 #[spacetimedb(table)]
 pub struct Board {
-    #[primary_key]
+    #[primarykey]
     pub game_id: u32,
+    pub width: u8,
+    pub height: u8,
+    pub seed: u64, // Generator seed, 0 for preset maps
     // Terrain stored as string: P=Plains, F=Forest, M=Mountain
-    // 25 characters representing 5x5 grid left-to-right, top-to-bottom
+    // "v2:<width>x<height>:" header, then width*height characters
+    // left-to-right, top-to-bottom (see `serialize_board`)
     pub terrain_data: String,
+    // Objectives as width*height characters in the same order:
+    // .=None, C=CapturePoint, H=HealingStation. Empty for boards without any.
+    pub objective_data: String,
 }
 
-/// Combat unit - one per player in Phase 1
+/// Named, hand-made map that games can be created on
 #[spacetimedb(table)]
+pub struct MapPreset {
+    #[primarykey]
+    pub name: String,
+    pub width: u8,
+    pub height: u8,
+    pub terrain_data: String, // Same format as Board.terrain_data
+    pub objective_data: Option<String>, // Same format as Board.objective_data; None for the standard layout
+    pub created_by: Option<Identity>, // None for built-in presets
+    pub created_at: u64,
+}
+
+/// Combat unit - each player fields the army they deployed.
+/// Private: players read units through their `SealedView`.
+#[spacetimedb(table(private))]
 pub struct Unit {
-    #[primary_key]
+    #[primarykey]
     #[autoinc]
     pub id: u32,
     pub game_id: u32,
     pub owner: Identity,
-    pub x: u8,              // 0-4 board position
-    pub y: u8,              // 0-4 board position
-    pub hp: i32,            // Health points (max 100)
-    pub movement_left: u8,  // Movement points this turn (max 3)
+    pub class: UnitClass,
+    pub x: u8,              // Column, 0..Board.width
+    pub y: u8,              // Row, 0..Board.height
+    pub hp: i32,            // Health points (max from class stats)
+    pub movement_left: u8,  // Movement points this turn (max from class stats)
     pub has_attacked: bool, // Can only attack once per turn
+    pub facing: Direction,  // Attacks from any other side are flanking
+    pub cooldowns: Vec<AbilityCooldown>,
+}
+
+/// A status effect on a unit, one row per effect. Private like `Unit`:
+/// players read effects through their `SealedView`.
+#[spacetimedb(table(private))]
+pub struct StatusEffect {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub unit_id: u32,
+    pub kind: EffectKind,
+    pub magnitude: i32,
+    pub turns_left: u8, // Counts down at the start of each of the owner's turns
+    pub source: EffectSource,
+}
+
+/// Capture point on a game's board and who currently holds it
+#[spacetimedb(table)]
+pub struct CapturePoint {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub x: u8,
+    pub y: u8,
+    pub owner: Option<Identity>, // Last player to end a turn on it
+}
+
+/// Key a player's `SealedView` rows are encrypted with, set through
+/// `set_view_key`. Private.
+#[spacetimedb(table(private))]
+pub struct ViewKey {
+    #[primarykey]
+    pub player: Identity,
+    pub key: Vec<u8>,    // VIEW_KEY_LENGTH bytes
+    pub next_nonce: u64, // Counts up so no nonce is used twice with a key
 }
 
-/// Log of all game actions for replay/debugging
+/// One player's `PlayerView` of a game, BSATN-encoded, zero-padded (see
+/// `sealing::pad`) and encrypted with their `ViewKey` using `nonce` (see
+/// `sealing::seal`). Rebuilt by `refresh_visibility`. Public tables can't be
+/// limited to some of their rows, so this is the only form per-player fog of
+/// war is published in.
 #[spacetimedb(table)]
+pub struct SealedView {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub viewer: Identity,
+    pub nonce: u64,
+    pub data: Vec<u8>,
+}
+
+/// A game whose public rows are behind its private ones after a move,
+/// attack, ability, preview or deployment. Private; `publish_games` catches
+/// them up in a transaction of its own, so the hidden arguments of those
+/// calls never reach subscribers.
+#[spacetimedb(table(private))]
+pub struct PendingPublish {
+    #[primarykey]
+    pub game_id: u32,
+}
+
+/// Latest `preview_attack` of each player, replaced by each new preview.
+/// Private: its target may be a unit only that player can see, so it is
+/// shown in their `SealedView` instead.
+#[spacetimedb(table(private))]
+pub struct CombatPreview {
+    #[primarykey]
+    pub player: Identity,
+    pub game_id: u32,
+    pub preview: AttackPreview,
+    pub created_at: u64,
+}
+
+/// Log of all game actions for replay/debugging.
+/// `sequence` numbers a game's actions from 0 in the order they happened.
+/// Private, as it shows every unit through the fog; it is copied to
+/// `PublishedAction` once the game finishes.
+#[spacetimedb(table(private))]
 pub struct GameAction {
-    #[primary_key]
+    #[primarykey]
     #[autoinc]
     pub id: u32,
     pub game_id: u32,
+    pub turn_number: u32,
+    pub sequence: u32,
     pub player: Identity,
-    pub action_type: String, // "move" or "attack"
-    pub details: String,     // JSON with action specifics
+    pub payload: ActionPayload,
     pub timestamp: u64,
 }
 
-// ===== HELPER FUNCTIONS =====
+/// `GameAction` rows of a finished game, published when it ends
+#[spacetimedb(table)]
+pub struct PublishedAction {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub turn_number: u32,
+    pub sequence: u32,
+    pub player: Identity,
+    pub payload: ActionPayload,
+    pub timestamp: u64,
+}
 
-/// Convert terrain type to defense value (damage reduction)
-fn get_terrain_defense(terrain: TerrainType) -> i32 {
-    match terrain {
-        TerrainType::Plains => 0,
-        TerrainType::Forest => 1,
-        TerrainType::Mountain => 2,
-    }
+/// Result of rebuilding a finished game from its action log with `build_replay`
+#[spacetimedb(table)]
+pub struct Replay {
+    #[primarykey]
+    pub game_id: u32,
+    pub frames: u32,
+    pub verified: bool,             // Rules reproduced every action and the final state
+    pub divergence: Option<String>, // First disagreement between log and rules, if any
+    pub built_at: u64,
 }
 
-/// Generate a 5x5 board with strategic terrain placement
-fn generate_random_board() -> Vec<Vec<TerrainType>> {
-    let mut board = vec![vec![TerrainType::Plains; 5]; 5];
-    
-    // Add forests for medium defense positions
-    board[1][1] = TerrainType::Forest;
-    board[3][3] = TerrainType::Forest;
-    board[2][1] = TerrainType::Forest;
-    board[1][3] = TerrainType::Forest;
-    board[3][1] = TerrainType::Forest;
-    
-    // Center mountain provides strategic high ground
-    board[2][2] = TerrainType::Mountain;
-    
-    board
+/// One step of a replay timeline: the action and the board right after it
+#[spacetimedb(table)]
+pub struct ReplayFrame {
+    #[primarykey]
+    #[autoinc]
+    pub id: u32,
+    pub game_id: u32,
+    pub sequence: u32,
+    pub turn_number: u32,
+    pub player: Identity,
+    pub payload: ActionPayload,
+    pub units: Vec<UnitSnapshot>,
+    pub team_points: Vec<u32>,
+}
+
+// ===== HELPER FUNCTIONS =====
+
+/// Build the board for a map choice and number of seats. Generated maps use
+/// `seed`; presets ignore it and record a seed of 0.
+fn resolve_map(game_id: u32, map: &MapChoice, seed: u64, seats: usize) -> Option<Board> {
+    let size = match map {
+        MapChoice::Small => 5,
+        MapChoice::Medium => 7,
+        MapChoice::Large => 9,
+        MapChoice::Preset(name) => {
+            let preset = MapPreset::filter_by_name(name)?;
+            let (width, height) = (preset.width, preset.height);
+            return Some(Board {
+                game_id,
+                width,
+                height,
+                seed: 0,
+                terrain_data: preset.terrain_data,
+                objective_data: preset
+                    .objective_data
+                    .unwrap_or_else(|| serialize_objectives(&default_objectives(width, height, seats))),
+            });
+        }
+    };
+    Some(Board {
+        game_id,
+        width: size,
+        height: size,
+        seed,
+        terrain_data: serialize_board(&generate_board(size, size, seed, seats)),
+        objective_data: serialize_objectives(&default_objectives(size, size, seats)),
+    })
 }
 
-fn serialize_board(board: &Vec<Vec<TerrainType>>) -> String {
-    // Simple serialization for MVP
-    let mut result = String::new();
-    for row in board {
-        for terrain in row {
-            match terrain {
-                TerrainType::Plains => result.push('P'),
-                TerrainType::Forest => result.push('F'),
-                TerrainType::Mountain => result.push('M'),
+/// Create the `CapturePoint` rows for a board's objectives
+fn spawn_capture_points(board: &Board) {
+    let objectives = deserialize_objectives(&board.objective_data, board.width, board.height);
+    for (y, row) in objectives.iter().enumerate() {
+        for (x, objective) in row.iter().enumerate() {
+            if *objective == ObjectiveType::CapturePoint {
+                CapturePoint::insert(CapturePoint {
+                    id: 0, // autoinc
+                    game_id: board.game_id,
+                    x: x as u8,
+                    y: y as u8,
+                    owner: None,
+                }).unwrap();
             }
         }
     }
-    result
 }
 
-fn deserialize_board(data: &str) -> Vec<Vec<TerrainType>> {
-    let mut board = vec![vec![TerrainType::Plains; 5]; 5];
-    let chars: Vec<char> = data.chars().collect();
+/// End a game. Every way a game can finish (elimination, victory points,
+/// round limit, surrender, timeouts) goes through here, after the final
+/// action is logged. A `None` winning team is a draw. Only duels are rated.
+fn finish_game(mut game: Game, winning_team: Option<Team>) -> Result<(), GameError> {
+    let game_id = game.id;
+    let players = seated_players(game_id);
+    let winners: Vec<Identity> = game_participants(game_id)
+        .iter()
+        .filter(|p| Some(p.team as Team) == winning_team)
+        .map(|p| p.player)
+        .collect();
+    let winner = if winners.len() == 1 { Some(winners[0]) } else { None };
+    game.status = GameStatus::Finished;
+    game.winner = winner;
+    game.winning_team = winning_team.map(|team| team as u8);
+    if let (GameMode::Duel, Some(player2)) = (game.mode, game.player2) {
+        update_ratings(game.player1, player2, winner);
+    }
+    Game::update_by_id(&game_id, game);
+    publish_log(game_id);
     
-    for i in 0..5 {
-        for j in 0..5 {
-            let idx = i * 5 + j;
-            if idx < chars.len() {
-                board[i][j] = match chars[idx] {
-                    'F' => TerrainType::Forest,
-                    'M' => TerrainType::Mountain,
-                    _ => TerrainType::Plains,
-                };
-            }
+    for id in players {
+        clear_current_game(id, game_id);
+    }
+    record_progression(game_id);
+    log::info!("Game {} finished, winning team: {:?}", game_id, winning_team);
+    record_tournament_result(game_id, winner)
+}
+
+/// Copy a finished game's action log to the public `PublishedAction` table
+fn publish_log(game_id: u32) {
+    for action in GameAction::iter().filter(|a| a.game_id == game_id) {
+        PublishedAction::insert(PublishedAction {
+            id: 0, // autoinc
+            game_id,
+            turn_number: action.turn_number,
+            sequence: action.sequence,
+            player: action.player,
+            payload: action.payload,
+            timestamp: action.timestamp,
+        }).unwrap();
+    }
+}
+
+/// Add a finished game to every player's career stats, XP and achievements
+fn record_progression(game_id: u32) {
+    let game = Game::filter_by_id(&game_id).unwrap();
+    let players = seated_players(game_id);
+    if players.len() < 2 {
+        return;
+    }
+    let board = Board::filter_by_game_id(&game_id).unwrap();
+    let mut actions: Vec<GameAction> = GameAction::iter().filter(|a| a.game_id == game_id).collect();
+    actions.sort_by_key(|a| a.sequence);
+    let log: Vec<(Seat, ActionPayload)> = actions
+        .into_iter()
+        .filter_map(|a| players.iter().position(|&p| p == a.player).map(|seat| (seat, a.payload)))
+        .collect();
+    
+    let summary = match summarize_game(starting_state(&board, &game.mode), &log) {
+        Ok(summary) => summary,
+        Err(e) => {
+            log::error!("Game {} can't be summarized: {}", game_id, e);
+            return;
+        }
+    };
+    for (seat, &player) in players.iter().enumerate() {
+        record_player_game(player, game_id, &summary, seat);
+    }
+}
+
+/// Add one player's side of a game summary to their career
+fn record_player_game(id: Identity, game_id: u32, summary: &GameSummary, seat: Seat) {
+    let mut player = match Player::filter_by_id(&id) {
+        Some(p) => p,
+        None => return,
+    };
+    let known = PlayerStats::filter_by_player(&id);
+    let first_game = known.is_none();
+    let mut stats = known.unwrap_or(PlayerStats {
+        player: id,
+        games_played: 0,
+        games_won: 0,
+        games_drawn: 0,
+        damage_dealt: 0,
+        damage_taken: 0,
+        units_killed: 0,
+        units_lost: 0,
+        attacks_by_terrain: Vec::new(),
+        favourite_terrain: None,
+    });
+    
+    let played = &summary.seats[seat];
+    stats.games_played += 1;
+    match summary.winner {
+        Some(_) if summary.won(seat) => stats.games_won += 1,
+        Some(_) => {}
+        None => stats.games_drawn += 1,
+    }
+    stats.damage_dealt += played.damage_dealt as u64;
+    stats.damage_taken += played.damage_taken as u64;
+    stats.units_killed += played.units_killed;
+    stats.units_lost += played.units_lost;
+    for &(terrain, attacks) in &played.attacks_from {
+        match stats.attacks_by_terrain.iter_mut().find(|t| t.terrain == terrain) {
+            Some(count) => count.attacks += attacks,
+            None => stats.attacks_by_terrain.push(TerrainCount { terrain, attacks }),
+        }
+    }
+    stats.favourite_terrain = stats.attacks_by_terrain.iter().max_by_key(|t| t.attacks).map(|t| t.terrain);
+    let games_played = stats.games_played;
+    if first_game {
+        PlayerStats::insert(stats).unwrap();
+    } else {
+        PlayerStats::update_by_player(&id, stats);
+    }
+    
+    player.xp += xp_for_game(summary, seat);
+    player.level = level_for_xp(player.xp);
+    Player::update_by_id(&id, player);
+    
+    for achievement in earned_achievements(summary, seat, games_played) {
+        if PlayerAchievement::iter().any(|a| a.player == id && a.achievement == achievement) {
+            continue;
+        }
+        PlayerAchievement::insert(PlayerAchievement {
+            id: 0, // autoinc
+            player: id,
+            achievement,
+            game_id,
+            unlocked_at: timestamp(),
+        }).unwrap();
+        log::info!("Player {:?} unlocked {:?}", id, achievement);
+    }
+}
+
+/// Apply the Elo result of a finished game to both players
+fn update_ratings(player1: Identity, player2: Identity, winner: Option<Identity>) {
+    let (mut p1, mut p2) = match (Player::filter_by_id(&player1), Player::filter_by_id(&player2)) {
+        (Some(p1), Some(p2)) => (p1, p2),
+        _ => return,
+    };
+    let score = match winner {
+        Some(w) if w == player1 => 1.0,
+        Some(_) => 0.0,
+        None => 0.5,
+    };
+    let change = elo_change(p1.rating, p2.rating, score);
+    p1.rating += change;
+    p2.rating -= change;
+    Player::update_by_id(&player1, p1);
+    Player::update_by_id(&player2, p2);
+}
+
+/// Record the game a player is now in and take them out of the matchmaking queue
+fn set_current_game(id: Identity, game_id: u32) {
+    MatchmakingQueue::delete_by_player(&id);
+    if let Some(mut player) = Player::filter_by_id(&id) {
+        player.current_game = Some(game_id);
+        Player::update_by_id(&id, player);
+    }
+}
+
+/// Forget a player's `current_game` if it's this game
+fn clear_current_game(id: Identity, game_id: u32) {
+    if let Some(mut player) = Player::filter_by_id(&id) {
+        if player.current_game == Some(game_id) {
+            player.current_game = None;
+            Player::update_by_id(&id, player);
+        }
+    }
+}
+
+/// Check that a player isn't playing or waiting in an unfinished game
+fn check_not_in_game(player: &Player) -> Result<(), GameError> {
+    if player.current_game.is_some() {
+        return Err(GameError::AlreadyInGame);
+    }
+    Ok(())
+}
+
+/// Fewest turns spectators can trail a game: a full round of its seats. Any
+/// less and a player watching their own game would see the latest turns of
+/// their opponents.
+fn min_spectator_delay(mode: &GameMode) -> u32 {
+    seat_teams(mode).len() as u32
+}
+
+/// Settings checked and filled in with defaults: (squad size, turn time in
+/// ms, spectator delay in turns)
+fn validate_settings(settings: &LobbySettings) -> Result<(u8, u64, u32), GameError> {
+    let squad_size = settings.squad_size.unwrap_or(DEFAULT_SQUAD_SIZE);
+    if !(MIN_SQUAD_SIZE..=MAX_SQUAD_SIZE).contains(&squad_size) {
+        return Err(GameError::InvalidSquadSize(squad_size));
+    }
+    
+    let turn_time_secs = settings.turn_time_secs.unwrap_or(DEFAULT_TURN_TIME_SECS);
+    if !(MIN_TURN_TIME_SECS..=MAX_TURN_TIME_SECS).contains(&turn_time_secs) {
+        return Err(GameError::InvalidTurnTime(turn_time_secs));
+    }
+    
+    if let GameMode::FreeForAll(players) = settings.mode {
+        if !(MIN_FREE_FOR_ALL_PLAYERS..=MAX_PLAYERS).contains(&players) {
+            return Err(GameError::InvalidPlayerCount(players));
+        }
+    }
+    
+    let min_delay = min_spectator_delay(&settings.mode);
+    let spectator_delay = settings.spectator_delay.unwrap_or(min_delay);
+    if spectator_delay < min_delay {
+        return Err(GameError::SpectatorDelayTooShort(min_delay));
+    }
+    
+    let smallest_edge = match &settings.map {
+        MapChoice::Preset(name) => {
+            let preset = MapPreset::filter_by_name(name).ok_or_else(|| GameError::MapNotFound(name.clone()))?;
+            preset.width.min(preset.height)
+        }
+        MapChoice::Small => 5,
+        _ => MIN_MULTIPLAYER_BOARD_SIZE,
+    };
+    let seats = seat_teams(&settings.mode).len();
+    if seats > 2 && smallest_edge < MIN_MULTIPLAYER_BOARD_SIZE {
+        return Err(GameError::InvalidMap(format!(
+            "{} players need a board of at least {1}x{1}",
+            seats, MIN_MULTIPLAYER_BOARD_SIZE
+        )));
+    }
+    Ok((squad_size, turn_time_secs as u64 * 1000, spectator_delay))
+}
+
+/// Hash of an invite code for a game. Codes ignore case and surrounding
+/// spaces, and the game id is mixed in so one code hashes differently per game.
+fn invite_code_hash(game_id: u32, code: &str) -> Vec<u8> {
+    let salted = format!("{}:{}", game_id, code.trim().to_uppercase());
+    hash_bytes(salted).to_vec()
+}
+
+/// Drop a game's invite code and the passes handed out with it
+fn remove_invite(game_id: u32) {
+    GameInvite::delete_by_game_id(&game_id);
+    for pass in InvitePass::filter_by_game_id(&game_id) {
+        InvitePass::delete_by_id(&pass.id);
+    }
+}
+
+/// Apply lobby visibility to a waiting game. Making it public removes its
+/// invite code; a private game's code is set with `set_invite_code`.
+fn set_visibility(game: &mut Game, visibility: &LobbyVisibility) {
+    match visibility {
+        LobbyVisibility::Public => {
+            game.private = false;
+            game.invited = None;
+            remove_invite(game.id);
+        }
+        LobbyVisibility::Private(invited) => {
+            game.private = true;
+            game.invited = *invited;
+        }
+    }
+}
+
+/// Create a waiting game with `player1` in the first seat. The board is set
+/// up separately.
+fn create_game_row(player1: Identity, mode: GameMode, squad_size: u8, turn_time_limit: u64) -> Game {
+    let game = Game {
+        id: 0, // autoinc
+        player1,
+        player2: None,
+        mode,
+        current_turn: player1,
+        status: GameStatus::WaitingForPlayers,
+        winner: None,
+        winning_team: None,
+        squad_size,
+        turn_number: 1,
+        round: 1,
+        team_points: vec![0; team_count(&mode)],
+        turn_time_limit,
+        turn_deadline: 0,
+        private: false,
+        invited: None,
+        rematch_of: None,
+        spectator_delay: min_spectator_delay(&mode),
+        created_at: timestamp(),
+    };
+    let game = Game::insert(game).unwrap();
+    insert_participant(&game, 0, player1);
+    game
+}
+
+/// Seat a player in a game's `GameParticipant` table
+fn insert_participant(game: &Game, seat: Seat, player: Identity) {
+    GameParticipant::insert(GameParticipant {
+        id: 0, // autoinc
+        game_id: game.id,
+        seat: seat as u8,
+        player,
+        team: seat_teams(&game.mode)[seat] as u8,
+        timeouts: 0,
+        eliminated: false,
+        deployed: false,
+        joined_at: timestamp(),
+    }).unwrap();
+}
+
+/// Store a waiting game's board and objectives. Armies are placed later, in
+/// the deployment phase.
+fn set_up_board(game: &Game, board: Board) {
+    spawn_capture_points(&board);
+    Board::insert(board).unwrap();
+    set_current_game(game.player1, game.id);
+    refresh_visibility(game.id);
+}
+
+/// Remove everything `set_up_board` created, so a waiting game can be set
+/// up again with new settings
+fn clear_board(game_id: u32) {
+    Board::delete_by_game_id(&game_id);
+    for unit in Unit::iter().filter(|u| u.game_id == game_id).collect::<Vec<_>>() {
+        Unit::delete_by_id(&unit.id);
+    }
+    for effect in StatusEffect::iter().filter(|e| e.game_id == game_id).collect::<Vec<_>>() {
+        StatusEffect::delete_by_id(&effect.id);
+    }
+    for point in CapturePoint::iter().filter(|p| p.game_id == game_id).collect::<Vec<_>>() {
+        CapturePoint::delete_by_id(&point.id);
+    }
+    for action in GameAction::iter().filter(|a| a.game_id == game_id).collect::<Vec<_>>() {
+        GameAction::delete_by_id(&action.id);
+    }
+    for view in SealedView::iter().filter(|v| v.game_id == game_id).collect::<Vec<_>>() {
+        SealedView::delete_by_id(&view.id);
+    }
+    for action in SpectatorAction::iter().filter(|a| a.game_id == game_id).collect::<Vec<_>>() {
+        SpectatorAction::delete_by_id(&action.id);
+    }
+    SpectatorReplay::delete_by_game_id(&game_id);
+    for pending in PendingDeployment::iter().filter(|d| d.game_id == game_id).collect::<Vec<_>>() {
+        PendingDeployment::delete_by_id(&pending.id);
+    }
+}
+
+/// Close a waiting game: everyone seated is free to join another game, and
+/// the game and everything set up for it are removed
+fn close_lobby(game: Game) {
+    let game_id = game.id;
+    for participant in game_participants(game_id) {
+        clear_current_game(participant.player, game_id);
+        GameParticipant::delete_by_id(&participant.id);
+    }
+    for bot in GameBot::iter().filter(|b| b.game_id == game_id).collect::<Vec<_>>() {
+        GameBot::delete_by_id(&bot.id);
+    }
+    for spectator in Spectator::iter().filter(|s| s.game_id == game_id).collect::<Vec<_>>() {
+        Spectator::delete_by_id(&spectator.id);
+    }
+    for seen in SpectatorUnit::iter().filter(|u| u.game_id == game_id).collect::<Vec<_>>() {
+        SpectatorUnit::delete_by_id(&seen.id);
+    }
+    remove_invite(game_id);
+    clear_board(game_id);
+    Game::delete_by_id(&game_id);
+    log::info!("Lobby {} closed", game_id);
+}
+
+/// Give up a seat of a waiting game. Whoever sat after it moves up one seat;
+/// bots are seated again so their squad starts from their new corner.
+fn free_seat(mut game: Game, seat: Seat) {
+    let game_id = game.id;
+    let participants = game_participants(game_id);
+    let bots: Vec<GameBot> = GameBot::iter().filter(|b| b.game_id == game_id).collect();
+    for participant in &participants[seat..] {
+        GameParticipant::delete_by_id(&participant.id);
+        if let Some(pending) = pending_deployment(game_id, participant.player) {
+            PendingDeployment::delete_by_id(&pending.id);
+        }
+    }
+    let leaver = participants[seat].player;
+    clear_current_game(leaver, game_id);
+    if seat == 1 {
+        game.player2 = None;
+    }
+    Game::update_by_id(&game_id, game);
+    log::info!("Player {:?} left game {}", leaver, game_id);
+    
+    for participant in &participants[seat + 1..] {
+        let game = Game::filter_by_id(&game_id).unwrap();
+        match bots.iter().find(|b| b.bot == participant.player) {
+            Some(bot) => {
+                GameBot::delete_by_id(&bot.id);
+                seat_bot(game, bot.difficulty);
+            }
+            None => seat_player(game, participant.player),
+        }
+    }
+}
+
+/// Random seed for a new board unless the client wants to replay a known one
+fn board_seed(game_id: u32, seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| timestamp() ^ (game_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// Create a game from lobby settings with `player1` seated. Returns the new game's id.
+fn start_game(player1: Identity, settings: &LobbySettings) -> Result<u32, GameError> {
+    let (squad_size, turn_time_limit, spectator_delay) = validate_settings(settings)?;
+    let mut game = create_game_row(player1, settings.mode, squad_size, turn_time_limit);
+    let game_id = game.id;
+    
+    let seats = seat_teams(&settings.mode).len();
+    let board = resolve_map(game_id, &settings.map, board_seed(game_id, settings.seed), seats).unwrap();
+    set_up_board(&game, board);
+    set_visibility(&mut game, &settings.visibility);
+    game.spectator_delay = spectator_delay;
+    Game::update_by_id(&game_id, game);
+    
+    log::info!("Game created: {}", game_id);
+    Ok(game_id)
+}
+
+/// Check that a player may take an open seat of a game. Private games
+/// need an `InvitePass` unless the player is the invited one.
+fn check_can_join(game: &Game, player: Identity) -> Result<(), GameError> {
+    let row = Player::filter_by_id(&player).ok_or(GameError::PlayerNotFound)?;
+    if game.status != GameStatus::WaitingForPlayers {
+        return Err(GameError::GameNotJoinable);
+    }
+    if seat_of(game, player).is_some() {
+        return Err(GameError::AlreadyInGame);
+    }
+    check_not_in_game(&row)?;
+    let has_pass = || InvitePass::filter_by_game_id(&game.id).any(|pass| pass.player == player);
+    if game.private && game.invited != Some(player) && !has_pass() {
+        return Err(GameError::InviteRequired);
+    }
+    Ok(())
+}
+
+/// Seat a player in the next open seat of a waiting game. Once every seat is
+/// taken the deployment phase starts, timed like a turn.
+fn seat_player(mut game: Game, player: Identity) {
+    let game_id = game.id;
+    let seat = seated_players(game_id).len();
+    insert_participant(&game, seat, player);
+    if seat == 1 {
+        game.player2 = Some(player);
+    }
+    if seat + 1 == seat_teams(&game.mode).len() {
+        game.status = GameStatus::Deployment;
+        game.turn_deadline = timestamp() + game.turn_time_limit;
+        remove_invite(game_id);
+    }
+    Game::update_by_id(&game_id, game);
+    set_current_game(player, game_id);
+    
+    log::info!("Player {:?} joined game {} in seat {}", player, game_id, seat + 1);
+}
+
+/// A player's locked-in army in a game, if they have locked one in
+fn pending_deployment(game_id: u32, player: Identity) -> Option<PendingDeployment> {
+    PendingDeployment::iter().find(|d| d.game_id == game_id && d.player == player)
+}
+
+/// Lock in a player's army. It stays hidden in `PendingDeployment`, and only
+/// private tables are written, until `start_battle_if_ready`.
+fn lock_deployment(game_id: u32, player: Identity, units: Vec<UnitPlacement>) -> Result<(), GameError> {
+    if !GameParticipant::iter().any(|p| p.game_id == game_id && p.player == player) {
+        return Err(GameError::NotInGame);
+    }
+    if pending_deployment(game_id, player).is_some() {
+        return Err(GameError::AlreadyDeployed);
+    }
+    PendingDeployment::insert(PendingDeployment {
+        id: 0, // autoinc
+        game_id,
+        player,
+        units,
+        locked_at: timestamp(),
+    }).unwrap();
+    log::info!("Player {:?} locked in their army for game {}", player, game_id);
+    Ok(())
+}
+
+/// Mark every seat of a game in deployment that has locked in as
+/// `deployed`. Once all have, place the armies, log them as `Join` actions
+/// in seat order and start the first turn.
+fn start_battle_if_ready(game_id: u32) {
+    let mut game = match Game::filter_by_id(&game_id) {
+        Some(g) if g.status == GameStatus::Deployment => g,
+        _ => return,
+    };
+    let mut ready = true;
+    for mut participant in game_participants(game_id) {
+        if participant.deployed {
+            continue;
+        }
+        if pending_deployment(game_id, participant.player).is_none() {
+            ready = false;
+            continue;
+        }
+        let id = participant.id;
+        participant.deployed = true;
+        GameParticipant::update_by_id(&id, participant);
+    }
+    if !ready {
+        return;
+    }
+    
+    let board = Board::filter_by_game_id(&game_id).unwrap();
+    for participant in game_participants(game_id) {
+        let pending = pending_deployment(game_id, participant.player).unwrap();
+        let units = spawn_army(&board, participant.player, participant.seat as Seat, &pending.units);
+        PendingDeployment::delete_by_id(&pending.id);
+        log_action(game_id, game.turn_number, participant.player, ActionPayload::Join(JoinAction { units }));
+    }
+    game.status = GameStatus::InProgress;
+    game.turn_deadline = timestamp() + game.turn_time_limit;
+    Game::update_by_id(&game_id, game);
+    refresh_visibility(game_id);
+    
+    log::info!("Game {} deployed, battle started", game_id);
+}
+
+/// The deployment phase ran out of time: everyone who hasn't locked in gets
+/// the default squad, and the battle starts
+fn time_out_deployment(game: Game) {
+    let game_id = game.id;
+    let board = Board::filter_by_game_id(&game_id).unwrap();
+    for participant in game_participants(game_id) {
+        if pending_deployment(game_id, participant.player).is_some() {
+            continue;
+        }
+        let units = default_deployment(board.width, board.height, game.squad_size, participant.seat as Seat);
+        if lock_deployment(game_id, participant.player, units).is_ok() {
+            log::info!("Player {:?} ran out of deployment time in game {}", participant.player, game_id);
+        }
+    }
+    start_battle_if_ready(game_id);
+}
+
+/// Participants of a tournament, best seed first
+fn tournament_participants(tournament_id: u32) -> Vec<TournamentParticipant> {
+    let mut participants: Vec<TournamentParticipant> =
+        TournamentParticipant::iter().filter(|p| p.tournament_id == tournament_id).collect();
+    participants.sort_by_key(|p| p.seed);
+    participants
+}
+
+/// Start round `number` of a tournament: pair the players, play byes out
+/// straight away and create a game for every other match
+fn start_round(tournament: &Tournament, number: u32) -> Result<(), GameError> {
+    let tournament_id = tournament.id;
+    let participants = tournament_participants(tournament_id);
+    let pairings: Vec<(Identity, Option<Identity>)> = match tournament.format {
+        TournamentFormat::SingleElimination if number == 1 => elimination_pairings(participants.len())
+            .into_iter()
+            .map(|(a, b)| (participants[a].player, b.map(|b| participants[b].player)))
+            .collect(),
+        TournamentFormat::SingleElimination => {
+            let mut previous: Vec<TournamentMatch> = TournamentMatch::iter()
+                .filter(|m| m.tournament_id == tournament_id && m.round == number - 1)
+                .collect();
+            previous.sort_by_key(|m| m.slot);
+            let winners: Vec<Identity> = previous.iter().filter_map(|m| m.winner).collect();
+            winners.chunks(2).map(|pair| (pair[0], pair.get(1).copied())).collect()
+        }
+        TournamentFormat::Swiss => {
+            let matches: Vec<TournamentMatch> =
+                TournamentMatch::iter().filter(|m| m.tournament_id == tournament_id).collect();
+            let standings: Vec<Standing> = participants
+                .iter()
+                .map(|p| Standing {
+                    seed: p.seed as usize,
+                    score: p.score,
+                    opponents: matches
+                        .iter()
+                        .filter_map(|m| match m.player2 {
+                            Some(p2) if m.player1 == p.player => Some(p2),
+                            Some(p2) if p2 == p.player => Some(m.player1),
+                            _ => None,
+                        })
+                        .filter_map(|o| participants.iter().find(|q| q.player == o).map(|q| q.seed as usize))
+                        .collect(),
+                    had_bye: p.had_bye,
+                })
+                .collect();
+            swiss_pairings(&standings)
+                .into_iter()
+                .map(|(a, b)| (participants[a].player, b.map(|b| participants[b].player)))
+                .collect()
+        }
+    };
+    
+    TournamentRound::insert(TournamentRound {
+        id: 0, // autoinc
+        tournament_id,
+        number,
+        finished: false,
+        started_at: timestamp(),
+    }).unwrap();
+    for (slot, (player1, player2)) in pairings.into_iter().enumerate() {
+        let game_id = match player2 {
+            Some(player2) => {
+                let game_id = start_game(player1, &tournament.settings)?;
+                seat_player(Game::filter_by_id(&game_id).unwrap(), player2);
+                Some(game_id)
+            }
+            None => {
+                award_tournament_points(tournament_id, player1, WIN_POINTS);
+                if let Some(mut participant) = TournamentParticipant::iter()
+                    .find(|p| p.tournament_id == tournament_id && p.player == player1)
+                {
+                    let id = participant.id;
+                    participant.had_bye = true;
+                    TournamentParticipant::update_by_id(&id, participant);
+                }
+                None
+            }
+        };
+        TournamentMatch::insert(TournamentMatch {
+            id: 0, // autoinc
+            tournament_id,
+            round: number,
+            slot: slot as u32,
+            player1,
+            player2,
+            game_id,
+            winner: if player2.is_none() { Some(player1) } else { None },
+            finished: player2.is_none(),
+        }).unwrap();
+    }
+    log::info!("Tournament {} round {} started", tournament_id, number);
+    Ok(())
+}
+
+/// Add tournament points to a participant
+fn award_tournament_points(tournament_id: u32, player: Identity, points: u32) {
+    if let Some(mut participant) =
+        TournamentParticipant::iter().find(|p| p.tournament_id == tournament_id && p.player == player)
+    {
+        let id = participant.id;
+        participant.score += points;
+        TournamentParticipant::update_by_id(&id, participant);
+    }
+}
+
+/// Record a finished game in its tournament match, if it has one, and move
+/// the tournament on once the whole round is done
+fn record_tournament_result(game_id: u32, winner: Option<Identity>) -> Result<(), GameError> {
+    let mut played = match TournamentMatch::iter().find(|m| m.game_id == Some(game_id)) {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    let tournament = Tournament::filter_by_id(&played.tournament_id).unwrap();
+    let tournament_id = tournament.id;
+    let (player1, player2) = (played.player1, played.player2.unwrap());
+    
+    let winner = match (tournament.format, winner) {
+        (_, Some(winner)) => Some(winner),
+        // Brackets need someone to go through: the better seed does
+        (TournamentFormat::SingleElimination, None) => {
+            let seed_of = |player: Identity| {
+                TournamentParticipant::iter()
+                    .find(|p| p.tournament_id == tournament_id && p.player == player)
+                    .map_or(u32::MAX, |p| p.seed)
+            };
+            Some(if seed_of(player1) <= seed_of(player2) { player1 } else { player2 })
+        }
+        (TournamentFormat::Swiss, None) => None,
+    };
+    match winner {
+        Some(winner) => {
+            award_tournament_points(tournament_id, winner, WIN_POINTS);
+            let loser = if winner == player1 { player2 } else { player1 };
+            if tournament.format == TournamentFormat::SingleElimination {
+                if let Some(mut participant) =
+                    TournamentParticipant::iter().find(|p| p.tournament_id == tournament_id && p.player == loser)
+                {
+                    let id = participant.id;
+                    participant.eliminated = true;
+                    TournamentParticipant::update_by_id(&id, participant);
+                }
+            }
+        }
+        None => {
+            award_tournament_points(tournament_id, player1, DRAW_POINTS);
+            award_tournament_points(tournament_id, player2, DRAW_POINTS);
+        }
+    }
+    let match_id = played.id;
+    let round = played.round;
+    played.winner = winner;
+    played.finished = true;
+    TournamentMatch::update_by_id(&match_id, played);
+    
+    let round_done = TournamentMatch::iter()
+        .filter(|m| m.tournament_id == tournament_id && m.round == round)
+        .all(|m| m.finished);
+    if round_done {
+        advance_tournament(tournament)?;
+    }
+    Ok(())
+}
+
+/// Close the current round and start the next one, or finish the tournament
+/// after the last round
+fn advance_tournament(mut tournament: Tournament) -> Result<(), GameError> {
+    let tournament_id = tournament.id;
+    let round = tournament.current_round;
+    if let Some(mut finished) =
+        TournamentRound::iter().find(|r| r.tournament_id == tournament_id && r.number == round)
+    {
+        let id = finished.id;
+        finished.finished = true;
+        TournamentRound::update_by_id(&id, finished);
+    }
+    
+    if round < tournament.total_rounds {
+        tournament.current_round = round + 1;
+        start_round(&tournament, round + 1)?;
+        Tournament::update_by_id(&tournament_id, tournament);
+        return Ok(());
+    }
+    
+    let winner = match tournament.format {
+        TournamentFormat::SingleElimination => TournamentMatch::iter()
+            .find(|m| m.tournament_id == tournament_id && m.round == round)
+            .and_then(|m| m.winner),
+        TournamentFormat::Swiss => {
+            let participants = tournament_participants(tournament_id);
+            let standings: Vec<Standing> = participants
+                .iter()
+                .map(|p| Standing { seed: p.seed as usize, score: p.score, opponents: Vec::new(), had_bye: p.had_bye })
+                .collect();
+            swiss_ranking(&standings).first().map(|&i| participants[i].player)
+        }
+    };
+    tournament.status = TournamentStatus::Finished;
+    tournament.winner = winner;
+    Tournament::update_by_id(&tournament_id, tournament);
+    log::info!("Tournament {} finished, winner: {:?}", tournament_id, winner);
+    Ok(())
+}
+
+/// Identity a bot plays under. Each difficulty gets one per seat, so two
+/// bots of the same difficulty can play each other. Real identities are
+/// hashes and won't collide with these.
+fn bot_identity(difficulty: BotDifficulty, seat: Seat) -> Identity {
+    let mut bytes = [0u8; 32];
+    bytes[0] = 0xB0;
+    bytes[1] = difficulty as u8 + 1;
+    bytes[2] = seat as u8 + 1;
+    Identity::from_byte_array(bytes)
+}
+
+/// Seat a bot in the next open seat of a waiting game. Bots lock in the
+/// default squad straight away.
+fn seat_bot(game: Game, difficulty: BotDifficulty) {
+    let game_id = game.id;
+    let squad_size = game.squad_size;
+    let seat = seated_players(game_id).len();
+    let bot = bot_identity(difficulty, seat);
+    GameBot::insert(GameBot { id: 0, game_id, bot, difficulty }).unwrap();
+    seat_player(game, bot);
+    
+    let board = Board::filter_by_game_id(&game_id).unwrap();
+    let units = default_deployment(board.width, board.height, squad_size, seat);
+    lock_deployment(game_id, bot, units).unwrap();
+    start_battle_if_ready(game_id);
+}
+
+/// Play a bot's whole turn: actions from `next_action` until it has nothing
+/// left to do, then end the turn. Each action is logged and saved like a
+/// player's, so replays and spectators see bot games the same way.
+fn play_bot_turn(game_id: u32, bot: Identity, difficulty: BotDifficulty) -> Result<(), GameError> {
+    loop {
+        let (game, seat) = match load_game(game_id, bot) {
+            Ok(loaded) => loaded,
+            Err(_) => return Ok(()),
+        };
+        if game.status != GameStatus::InProgress || game.current_turn != bot {
+            return Ok(());
+        }
+        let turn_number = game.turn_number;
+        let mut state = load_state(&game);
+        
+        let result = match next_action(&state, seat, difficulty) {
+            Some(BotAction::Move { unit_id, to }) => state.move_unit(seat, unit_id, to).map(ActionPayload::Move),
+            Some(BotAction::Attack { attacker_id, target_id }) => {
+                state.attack(seat, attacker_id, target_id).map(ActionPayload::Attack)
+            }
+            None => state.end_turn(seat).map(|_| ActionPayload::EndTurn),
+        };
+        let payload = match result {
+            Ok(payload) => payload,
+            Err(e) => {
+                // Shouldn't happen; ending the turn keeps the game going
+                log::error!("Bot action rejected in game {}: {}", game_id, e);
+                if state.end_turn(seat).is_err() {
+                    return Ok(());
+                }
+                ActionPayload::EndTurn
+            }
+        };
+        let turn_over = payload == ActionPayload::EndTurn;
+        log_action(game_id, turn_number, bot, payload);
+        save_state(game, &state)?;
+        refresh_visibility(game_id);
+        if turn_over {
+            return Ok(());
+        }
+    }
+}
+
+/// Balance numbers of an ability definition, as used and logged
+fn ability_spec(definition: &AbilityDefinition) -> AbilitySpec {
+    AbilitySpec {
+        name: definition.name.clone(),
+        kind: definition.kind,
+        range: definition.range,
+        power: definition.power,
+        cooldown: definition.cooldown,
+    }
+}
+
+/// Add or replace an ability definition
+fn put_ability(class: UnitClass, spec: AbilitySpec, enabled: bool) {
+    AbilityDefinition::delete_by_name(&spec.name);
+    AbilityDefinition::insert(AbilityDefinition {
+        name: spec.name,
+        class,
+        kind: spec.kind,
+        range: spec.range,
+        power: spec.power,
+        cooldown: spec.cooldown,
+        enabled,
+        updated_at: timestamp(),
+    }).unwrap();
+}
+
+fn check_admin(identity: Identity) -> Result<(), GameError> {
+    match Admin::filter_by_identity(&identity) {
+        Some(_) => Ok(()),
+        None => Err(GameError::NotAdmin),
+    }
+}
+
+/// Settings of games started by matchmaking
+fn matchmaking_settings() -> LobbySettings {
+    LobbySettings {
+        squad_size: None,
+        map: MATCHMAKING_MAP,
+        mode: GameMode::Duel,
+        seed: None,
+        turn_time_secs: None,
+        visibility: LobbyVisibility::Public,
+        spectator_delay: None,
+    }
+}
+
+/// Rebuild every player's `PlayerView` of a game and seal it into their
+/// `SealedView`, then bring its spectators up to date. Called after anything
+/// that moves, spawns, damages or resets units, once the action is logged.
+fn refresh_visibility(game_id: u32) {
+    let game = match Game::filter_by_id(&game_id) {
+        Some(g) => g,
+        None => return,
+    };
+    let state = load_state(&game);
+    let units: Vec<Unit> = Unit::iter().filter(|u| u.game_id == game_id).collect();
+    let effects: Vec<StatusEffect> = StatusEffect::iter().filter(|e| e.game_id == game_id).collect();
+    let seats = seated_players(game_id);
+    let team_of = |owner: Identity| seats.iter().position(|&p| p == owner).map(|seat| state.teams[seat]);
+    
+    for (seat, &player) in seats.iter().enumerate() {
+        let visible_tiles = state.visible_tiles(seat);
+        
+        // The team's units are always known, including dead ones; others only when seen alive
+        let seen = units
+            .iter()
+            .filter(|u| team_of(u.owner) == Some(state.teams[seat]) || (u.hp > 0 && visible_tiles.contains(&(u.x, u.y))))
+            .map(|unit| VisibleUnit {
+                unit_id: unit.id,
+                owner: unit.owner,
+                class: unit.class,
+                x: unit.x,
+                y: unit.y,
+                hp: unit.hp,
+                movement_left: unit.movement_left,
+                has_attacked: unit.has_attacked,
+                facing: unit.facing,
+                effects: unit_effects(&effects, unit.id),
+                cooldowns: if unit.owner == player { unit.cooldowns.clone() } else { Vec::new() },
+            })
+            .collect();
+        publish_view(player, PlayerView {
+            game_id,
+            turn_number: game.turn_number,
+            tiles: visible_tiles.into_iter().map(|(x, y)| Position { x, y }).collect(),
+            units: seen,
+            preview: CombatPreview::filter_by_player(&player).filter(|p| p.game_id == game_id).map(|p| p.preview),
+        });
+    }
+    refresh_spectator_view(game_id);
+}
+
+/// Have `publish_games` catch up a game's public rows after a move, attack,
+/// ability, preview or deployment. Those reducers only write private tables
+/// themselves.
+fn queue_publish(game_id: u32) {
+    if PendingPublish::filter_by_game_id(&game_id).is_none() {
+        PendingPublish::insert(PendingPublish { game_id }).unwrap();
+        spacetimedb::schedule!("0ms", publish_games(_));
+    }
+}
+
+/// Seal a player's view with their `ViewKey` into their `SealedView` row.
+/// Players who haven't set a key, bots included, get no row.
+fn publish_view(viewer: Identity, view: PlayerView) {
+    let mut view_key = match ViewKey::filter_by_player(&viewer) {
+        Some(k) => k,
+        None => return,
+    };
+    let key: [u8; VIEW_KEY_LENGTH] = view_key.key.as_slice().try_into().unwrap();
+    let nonce = view_key.next_nonce;
+    view_key.next_nonce += 1;
+    ViewKey::update_by_player(&viewer, view_key);
+    
+    let game_id = view.game_id;
+    let data = seal(&key, nonce, &pad(bsatn::to_vec(&view).unwrap()));
+    match SealedView::iter().find(|v| v.game_id == game_id && v.viewer == viewer) {
+        Some(old) => {
+            SealedView::update_by_id(&old.id, SealedView { nonce, data, ..old });
+        }
+        None => {
+            SealedView::insert(SealedView { id: 0, game_id, viewer, nonce, data }).unwrap();
+        }
+    }
+}
+
+/// A game on this board before anyone has joined, for replaying its log
+fn starting_state(board: &Board, mode: &GameMode) -> GameState {
+    let terrain = deserialize_board(&board.terrain_data);
+    let objectives = deserialize_objectives(&board.objective_data, board.width, board.height);
+    GameState::new(terrain, objectives).with_teams(seat_teams(mode))
+}
+
+/// Load the spectators' replay of a game, or start one from the board.
+/// Returns it with the sequence of the first action it hasn't seen.
+fn load_replay(game: &Game, seats: &[Identity]) -> (u32, GameState) {
+    let board = Board::filter_by_game_id(&game.id).unwrap();
+    let mut state = starting_state(&board, &game.mode);
+    let replay = match SpectatorReplay::filter_by_game_id(&game.id) {
+        Some(r) => r,
+        None => return (0, state),
+    };
+    
+    state.units = replay
+        .units
+        .into_iter()
+        .map(|u| UnitState {
+            id: u.unit_id,
+            owner: seats.iter().position(|&p| p == u.owner).unwrap(),
+            class: u.class,
+            x: u.x,
+            y: u.y,
+            hp: u.hp,
+            movement_left: u.movement_left,
+            has_attacked: u.has_attacked,
+            facing: u.facing,
+            effects: u.effects,
+            cooldowns: u.cooldowns,
+        })
+        .collect();
+    for (point, owner) in state.capture_points.iter_mut().zip(replay.capture_owners) {
+        point.owner = owner.map(|seat| seat as Seat);
+    }
+    state.players_joined = replay.players_joined as usize;
+    state.current_turn = replay.current_seat as Seat;
+    state.turn_number = replay.turn_number;
+    state.round = replay.round;
+    state.points = replay.team_points;
+    state.status = replay.status;
+    state.winner = replay.winning_team.map(|team| team as Team);
+    (replay.next_sequence, state)
+}
+
+/// Store the spectators' replay of a game for the next refresh
+fn save_replay(game_id: u32, next_sequence: u32, seats: &[Identity], state: &GameState) {
+    let replay = SpectatorReplay {
+        game_id,
+        next_sequence,
+        units: state
+            .units
+            .iter()
+            .map(|u| VisibleUnit {
+                unit_id: u.id,
+                owner: seats[u.owner],
+                class: u.class,
+                x: u.x,
+                y: u.y,
+                hp: u.hp,
+                movement_left: u.movement_left,
+                has_attacked: u.has_attacked,
+                facing: u.facing,
+                effects: u.effects.clone(),
+                cooldowns: u.cooldowns.clone(),
+            })
+            .collect(),
+        capture_owners: state.capture_points.iter().map(|p| p.owner.map(|seat| seat as u8)).collect(),
+        players_joined: state.players_joined as u8,
+        current_seat: state.current_turn as u8,
+        turn_number: state.turn_number,
+        round: state.round,
+        team_points: state.points.clone(),
+        status: state.status,
+        winning_team: state.winner.map(|team| team as u8),
+    };
+    if SpectatorReplay::filter_by_game_id(&game_id).is_some() {
+        SpectatorReplay::update_by_game_id(&game_id, replay);
+    } else {
+        SpectatorReplay::insert(replay).unwrap();
+    }
+}
+
+/// Bring the spectators' replay of a game up to the end of `last_turn`,
+/// applying only the actions it hasn't seen yet
+fn advance_replay(game: &Game, last_turn: u32) -> GameState {
+    let seats = seated_players(game.id);
+    let (mut next_sequence, mut state) = load_replay(game, &seats);
+    
+    let mut actions: Vec<GameAction> = GameAction::iter()
+        .filter(|a| a.game_id == game.id && a.sequence >= next_sequence && a.turn_number <= last_turn)
+        .collect();
+    if actions.is_empty() {
+        return state;
+    }
+    actions.sort_by_key(|a| a.sequence);
+    for action in actions {
+        let result = match seats.iter().position(|&p| p == action.player) {
+            Some(seat) => state.apply(seat, &action.payload),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            log::error!("Game {} action {} does not replay: {}", game.id, action.sequence, e);
+            break;
+        }
+        next_sequence = action.sequence + 1;
+    }
+    save_replay(game.id, next_sequence, &seats, &state);
+    state
+}
+
+/// Rebuild what the spectators of a game see. Until the game finishes they
+/// trail it by `spectator_delay` whole turns, so nothing they could pass on
+/// is still news; afterwards everything is shown.
+fn refresh_spectator_view(game_id: u32) {
+    let game = match Game::filter_by_id(&game_id) {
+        Some(g) => g,
+        None => return,
+    };
+    let mut perspectives: Vec<SpectatorPerspective> = Vec::new();
+    for spectator in Spectator::iter().filter(|s| s.game_id == game_id) {
+        if !perspectives.contains(&spectator.perspective) {
+            perspectives.push(spectator.perspective);
+        }
+    }
+    for seen in SpectatorUnit::iter().filter(|u| u.game_id == game_id).collect::<Vec<_>>() {
+        SpectatorUnit::delete_by_id(&seen.id);
+    }
+    if perspectives.is_empty() {
+        return;
+    }
+    
+    let last_turn = if game.status == GameStatus::Finished {
+        u32::MAX
+    } else {
+        game.turn_number.saturating_sub(game.spectator_delay)
+    };
+    let state = advance_replay(&game, last_turn);
+    let seats = seated_players(game_id);
+    
+    for perspective in perspectives {
+        // A player's perspective shows their team's units plus living enemies they can see
+        let visible = match perspective {
+            SpectatorPerspective::Full => None,
+            SpectatorPerspective::Player(player) => seat_of(&game, player).map(|seat| (seat, state.visible_tiles(seat))),
+        };
+        let shown = state.units.iter().filter(|u| match &visible {
+            None => true,
+            Some((seat, tiles)) => state.teams[u.owner] == state.teams[*seat] || (u.hp > 0 && tiles.contains(&(u.x, u.y))),
+        });
+        for unit in shown {
+            SpectatorUnit::insert(SpectatorUnit {
+                id: 0, // autoinc
+                game_id,
+                perspective,
+                unit_id: unit.id,
+                owner: seats[unit.owner],
+                class: unit.class,
+                x: unit.x,
+                y: unit.y,
+                hp: unit.hp,
+                facing: unit.facing,
+                as_of_turn: state.turn_number,
+            }).unwrap();
+        }
+    }
+    
+    // Actions only ever become visible, so just append the new ones
+    let shown = SpectatorAction::iter().filter(|a| a.game_id == game_id).count() as u32;
+    let mut actions: Vec<GameAction> = GameAction::iter()
+        .filter(|a| a.game_id == game_id && a.sequence >= shown && a.turn_number <= last_turn)
+        .collect();
+    actions.sort_by_key(|a| a.sequence);
+    for action in actions {
+        SpectatorAction::insert(SpectatorAction {
+            id: 0, // autoinc
+            game_id,
+            sequence: action.sequence,
+            turn_number: action.turn_number,
+            player: action.player,
+            payload: action.payload,
+        }).unwrap();
+    }
+}
+
+/// Create the units of a locked-in army, facing across the board: top
+/// corners face South, bottom corners North
+fn spawn_army(board: &Board, owner: Identity, seat: Seat, army: &[UnitPlacement]) -> Vec<SpawnedUnit> {
+    let game_id = board.game_id;
+    let facing = if seat.is_multiple_of(2) { Direction::South } else { Direction::North };
+    let mut spawned = Vec::new();
+    for &UnitPlacement { class, position: Position { x, y } } in army {
+        let stats = get_class_stats(class);
+        let unit = Unit {
+            id: 0, // autoinc
+            game_id,
+            owner,
+            class,
+            x,
+            y,
+            hp: stats.max_hp,
+            movement_left: stats.movement,
+            has_attacked: false,
+            facing,
+            cooldowns: Vec::new(),
+        };
+        let unit = Unit::insert(unit).unwrap();
+        spawned.push(SpawnedUnit {
+            unit_id: unit.id,
+            class,
+            position: Position { x, y },
+            facing,
+        });
+    }
+    spawned
+}
+
+/// Append an action to a game's log with the next sequence number
+fn log_action(game_id: u32, turn_number: u32, player: Identity, payload: ActionPayload) {
+    let sequence = GameAction::iter().filter(|a| a.game_id == game_id).count() as u32;
+    let action = GameAction {
+        id: 0, // autoinc
+        game_id,
+        turn_number,
+        sequence,
+        player,
+        payload,
+        timestamp: timestamp(),
+    };
+    GameAction::insert(action).unwrap();
+}
+
+/// Participants of a game in seat order
+fn game_participants(game_id: u32) -> Vec<GameParticipant> {
+    let mut participants: Vec<GameParticipant> = GameParticipant::iter().filter(|p| p.game_id == game_id).collect();
+    participants.sort_by_key(|p| p.seat);
+    participants
+}
+
+/// Players of a game indexed by seat
+fn seated_players(game_id: u32) -> Vec<Identity> {
+    game_participants(game_id).into_iter().map(|p| p.player).collect()
+}
+
+/// Seat a player occupies in a game, if they're in it
+fn seat_of(game: &Game, player: Identity) -> Option<Seat> {
+    GameParticipant::iter()
+        .find(|p| p.game_id == game.id && p.player == player)
+        .map(|p| p.seat as Seat)
+}
+
+/// Player sitting in a seat
+fn player_in_seat(game: &Game, seat: Seat) -> Option<Identity> {
+    GameParticipant::iter()
+        .find(|p| p.game_id == game.id && p.seat as Seat == seat)
+        .map(|p| p.player)
+}
+
+/// Snapshot every unit of a replay state, with seats mapped back to players
+fn snapshot_units(seats: &[Identity], state: &GameState) -> Vec<UnitSnapshot> {
+    state
+        .units
+        .iter()
+        .map(|u| UnitSnapshot {
+            unit_id: u.id,
+            owner: seats[u.owner],
+            class: u.class,
+            position: Position { x: u.x, y: u.y },
+            hp: u.hp,
+            facing: u.facing,
+        })
+        .collect()
+}
+
+/// Replay a game's log from its starting board. Writes one `ReplayFrame` per
+/// action and returns the frame count, or the first divergence.
+fn replay_game(game: &Game, board: &Board) -> (u32, Result<GameState, String>) {
+    let mut state = starting_state(board, &game.mode);
+    let seats = seated_players(game.id);
+    
+    let mut actions: Vec<GameAction> = GameAction::iter().filter(|a| a.game_id == game.id).collect();
+    actions.sort_by_key(|a| a.sequence);
+    
+    let mut frames = 0;
+    for (expected, action) in actions.into_iter().enumerate() {
+        if action.sequence != expected as u32 {
+            return (frames, Err(format!("Action {} missing from the log", expected)));
+        }
+        if action.turn_number != state.turn_number {
+            return (frames, Err(format!("Action {} logged on turn {}, replay is on turn {}", action.sequence, action.turn_number, state.turn_number)));
+        }
+        let seat = match seats.iter().position(|&p| p == action.player) {
+            Some(seat) => seat,
+            None => return (frames, Err(format!("Action {} by a player not in the game", action.sequence))),
+        };
+        if let Err(e) = state.apply(seat, &action.payload) {
+            return (frames, Err(format!("Action {}: {}", action.sequence, e)));
+        }
+        
+        ReplayFrame::insert(ReplayFrame {
+            id: 0, // autoinc
+            game_id: game.id,
+            sequence: action.sequence,
+            turn_number: action.turn_number,
+            player: action.player,
+            payload: action.payload,
+            units: snapshot_units(&seats, &state),
+            team_points: state.points.clone(),
+        }).unwrap();
+        frames += 1;
+    }
+    (frames, Ok(state))
+}
+
+/// Compare a replayed final state with what the tables hold
+fn check_final_state(game: &Game, state: &GameState) -> Result<(), String> {
+    if state.status != game.status || state.winner.map(|team| team as u8) != game.winning_team {
+        return Err(format!("Replay ends {:?} with winning team {:?}, game is {:?} with winning team {:?}",
+            state.status, state.winner, game.status, game.winning_team));
+    }
+    for unit in Unit::iter().filter(|u| u.game_id == game.id) {
+        let replayed = state.units.iter().find(|r| r.id == unit.id);
+        let matches = replayed.is_some_and(|r| (r.x, r.y, r.hp) == (unit.x, unit.y, unit.hp));
+        if !matches {
+            return Err(format!("Unit {} ends at {},{} with {} HP, replay disagrees", unit.id, unit.x, unit.y, unit.hp));
+        }
+    }
+    Ok(())
+}
+
+/// Effects on one unit, from the game's `StatusEffect` rows
+fn unit_effects(effects: &[StatusEffect], unit_id: u32) -> Vec<ActiveEffect> {
+    effects
+        .iter()
+        .filter(|e| e.unit_id == unit_id)
+        .map(|e| ActiveEffect {
+            kind: e.kind,
+            magnitude: e.magnitude,
+            turns_left: e.turns_left,
+            source: e.source.clone(),
+        })
+        .collect()
+}
+
+/// Rules-side view of a unit row and its effects, given the game's players by seat
+fn unit_state(seats: &[Identity], unit: &Unit, effects: &[StatusEffect]) -> UnitState {
+    UnitState {
+        id: unit.id,
+        owner: seats.iter().position(|&p| p == unit.owner).unwrap(),
+        class: unit.class,
+        x: unit.x,
+        y: unit.y,
+        hp: unit.hp,
+        movement_left: unit.movement_left,
+        has_attacked: unit.has_attacked,
+        facing: unit.facing,
+        effects: unit_effects(effects, unit.id),
+        cooldowns: unit.cooldowns.clone(),
+    }
+}
+
+/// Load a game's tables into a `GameState` for the rules to work on. A game
+/// whose last attack hasn't been published yet (see `PendingPublish`) is
+/// settled here, so nobody can act in a game that is already over.
+fn load_state(game: &Game) -> GameState {
+    let board = Board::filter_by_game_id(&game.id).unwrap();
+    let seats = seated_players(game.id);
+    let seat_of_player = |player: Identity| seats.iter().position(|&p| p == player);
+    let mut state = starting_state(&board, &game.mode);
+    
+    let effects: Vec<StatusEffect> = StatusEffect::iter().filter(|e| e.game_id == game.id).collect();
+    state.units = Unit::iter().filter(|u| u.game_id == game.id).map(|u| unit_state(&seats, &u, &effects)).collect();
+    state.capture_points = CapturePoint::iter()
+        .filter(|p| p.game_id == game.id)
+        .map(|p| CaptureState { x: p.x, y: p.y, owner: p.owner.and_then(seat_of_player) })
+        .collect();
+    state.players_joined = seats.len();
+    state.current_turn = seat_of_player(game.current_turn).unwrap();
+    state.turn_number = game.turn_number;
+    state.round = game.round;
+    state.points = game.team_points.clone();
+    state.status = game.status;
+    state.winner = game.winning_team.map(|team| team as Team);
+    state.check_last_team_standing();
+    state
+}
+
+/// Write the units of a `GameState` back to the private `Unit` and
+/// `StatusEffect` tables, touching only units that changed; a unit's effects
+/// are rewritten together
+fn save_units(game_id: u32, seats: &[Identity], state: &GameState) {
+    let effects: Vec<StatusEffect> = StatusEffect::iter().filter(|e| e.game_id == game_id).collect();
+    for unit in Unit::iter().filter(|u| u.game_id == game_id).collect::<Vec<_>>() {
+        let updated = match state.units.iter().find(|s| s.id == unit.id) {
+            Some(s) if *s != unit_state(seats, &unit, &effects) => s,
+            _ => continue,
+        };
+        if updated.effects != unit_effects(&effects, unit.id) {
+            for old in effects.iter().filter(|e| e.unit_id == unit.id) {
+                StatusEffect::delete_by_id(&old.id);
+            }
+            for effect in &updated.effects {
+                StatusEffect::insert(StatusEffect {
+                    id: 0, // autoinc
+                    game_id,
+                    unit_id: unit.id,
+                    kind: effect.kind,
+                    magnitude: effect.magnitude,
+                    turns_left: effect.turns_left,
+                    source: effect.source.clone(),
+                }).unwrap();
+            }
+        }
+        Unit::update_by_id(&unit.id, Unit {
+            id: unit.id,
+            game_id: unit.game_id,
+            owner: unit.owner,
+            class: updated.class,
+            x: updated.x,
+            y: updated.y,
+            hp: updated.hp,
+            movement_left: updated.movement_left,
+            has_attacked: updated.has_attacked,
+            facing: updated.facing,
+            cooldowns: updated.cooldowns.clone(),
+        });
+    }
+}
+
+/// Write a `GameState` back to the game's tables, touching only rows that
+/// changed. Newly eliminated players are marked and freed to play elsewhere,
+/// a new turn gets a fresh deadline, and a state that just finished goes
+/// through `finish_game`.
+fn save_state(mut game: Game, state: &GameState) -> Result<(), GameError> {
+    let seats = seated_players(game.id);
+    save_units(game.id, &seats, state);
+    
+    for mut point in CapturePoint::iter().filter(|p| p.game_id == game.id).collect::<Vec<_>>() {
+        let owner = state
+            .capture_points
+            .iter()
+            .find(|c| (c.x, c.y) == (point.x, point.y))
+            .and_then(|c| c.owner)
+            .map(|seat| seats[seat]);
+        if owner != point.owner {
+            log::info!("Capture point {},{} taken by {:?}", point.x, point.y, owner);
+            point.owner = owner;
+            let id = point.id;
+            CapturePoint::update_by_id(&id, point);
+        }
+    }
+    
+    for mut participant in game_participants(game.id) {
+        if participant.eliminated || !state.is_eliminated(participant.seat as Seat) {
+            continue;
         }
+        let (id, player) = (participant.id, participant.player);
+        participant.eliminated = true;
+        GameParticipant::update_by_id(&id, participant);
+        clear_current_game(player, game.id);
+        log::info!("Player {:?} eliminated from game {}", player, game.id);
     }
     
-    board
+    let changed = seats[state.current_turn] != game.current_turn
+        || state.turn_number != game.turn_number
+        || state.round != game.round
+        || state.points != game.team_points;
+    if state.turn_number != game.turn_number {
+        game.turn_deadline = timestamp() + game.turn_time_limit;
+    }
+    game.current_turn = seats[state.current_turn];
+    game.turn_number = state.turn_number;
+    game.round = state.round;
+    game.team_points = state.points.clone();
+    if state.status == GameStatus::Finished && game.status != GameStatus::Finished {
+        finish_game(game, state.winner)?;
+    } else if changed {
+        let game_id = game.id;
+        Game::update_by_id(&game_id, game);
+    }
+    Ok(())
 }
 
-/// Check if two positions are adjacent (no diagonal)
-fn is_adjacent(x1: u8, y1: u8, x2: u8, y2: u8) -> bool {
-    let dx = (x1 as i32 - x2 as i32).abs();
-    let dy = (y1 as i32 - y2 as i32).abs();
-    (dx == 1 && dy == 0) || (dx == 0 && dy == 1)
+/// Game and acting seat for a reducer call
+fn load_game(game_id: u32, player: Identity) -> Result<(Game, Seat), GameError> {
+    let game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
+    let seat = seat_of(&game, player).ok_or(GameError::NotInGame)?;
+    Ok((game, seat))
 }
 
-/// Calculate Manhattan distance for movement
-fn calculate_distance(x1: u8, y1: u8, x2: u8, y2: u8) -> u8 {
-    ((x1 as i32 - x2 as i32).abs() + (y1 as i32 - y2 as i32).abs()) as u8
+/// The current player ran out of time: end their turn for them, or forfeit
+/// for them once it's happened `MAX_CONSECUTIVE_TIMEOUTS` turns in a row.
+/// Forfeiting eliminates the player, which ends a duel.
+fn time_out_turn(game: Game) -> Result<(), GameError> {
+    let game_id = game.id;
+    let turn_number = game.turn_number;
+    let player = game.current_turn;
+    let mut participant = match GameParticipant::iter().find(|p| p.game_id == game_id && p.player == player) {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    let seat = participant.seat as Seat;
+    let forfeit = participant.timeouts + 1 >= MAX_CONSECUTIVE_TIMEOUTS;
+    
+    let mut state = load_state(&game);
+    let (result, payload) = if forfeit {
+        (state.surrender(seat), ActionPayload::Forfeit)
+    } else {
+        (state.end_turn(seat), ActionPayload::Timeout)
+    };
+    result?;
+    let participant_id = participant.id;
+    participant.timeouts += 1;
+    GameParticipant::update_by_id(&participant_id, participant);
+    log_action(game_id, turn_number, player, payload);
+    save_state(game, &state)?;
+    refresh_visibility(game_id);
+    
+    if forfeit {
+        log::info!("Player {:?} forfeited game {} after {} timeouts", player, game_id, MAX_CONSECUTIVE_TIMEOUTS);
+    } else {
+        log::info!("Player {:?} timed out on turn {} of game {}", player, turn_number, game_id);
+    }
+    Ok(())
 }
 
 // ===== REDUCERS =====
 
 /// Create a new player account
 #[spacetimedb(reducer)]
-pub fn create_player(ctx: ReducerContext, username: String) {
+pub fn create_player(ctx: ReducerContext, username: String) -> Result<(), GameError> {
     // Check if player already exists
     if Player::filter_by_id(&ctx.sender).is_some() {
-        log::info!("Player already exists: {:?}", ctx.sender);
-        return;
+        return Err(GameError::PlayerAlreadyExists);
     }
     
     let player = Player {
         id: ctx.sender,
         username,
-        created_at: spacetimedb::timestamp(),
+        rating: STARTING_RATING,
+        current_game: None,
+        xp: 0,
+        level: 1,
+        created_at: timestamp(),
     };
-    Player::insert(player);
+    Player::insert(player).unwrap();
     log::info!("Player created: {:?}", ctx.sender);
+    Ok(())
 }
 
-/// Create a new game session - player becomes player1
+/// Set the key your `SealedView` rows are encrypted with: VIEW_KEY_LENGTH
+/// random bytes your client keeps to itself. Only a private table changes,
+/// so the call isn't passed on to other clients; views are sealed with the
+/// new key from the next change to the game.
 #[spacetimedb(reducer)]
-pub fn create_game(ctx: ReducerContext) {
-    // Check if player exists
+pub fn set_view_key(ctx: ReducerContext, key: Vec<u8>) -> Result<(), GameError> {
     if Player::filter_by_id(&ctx.sender).is_none() {
-        log::error!("Player not found: {:?}", ctx.sender);
-        return;
+        return Err(GameError::PlayerNotFound);
+    }
+    if key.len() != VIEW_KEY_LENGTH {
+        return Err(GameError::InvalidViewKey(key.len()));
     }
     
-    // Create new game
-    let game = Game {
-        id: 0, // autoinc
-        player1: ctx.sender,
-        player2: None,
-        current_turn: ctx.sender,
-        status: GameStatus::WaitingForPlayers,
-        winner: None,
-        created_at: spacetimedb::timestamp(),
-    };
-    Game::insert(game).unwrap();
-    
-    // Get the created game
-    let created_game = Game::filter_by_player1(&ctx.sender)
-        .find(|g| g.status == GameStatus::WaitingForPlayers)
-        .unwrap();
-    
-    // Create board
-    let board_data = serialize_board(&generate_random_board());
-    let board = Board {
-        game_id: created_game.id,
-        terrain_data: board_data,
-    };
-    Board::insert(board);
-    
-    // Create player 1's unit at corner (0,0)
-    let unit1 = Unit {
-        id: 0, // autoinc
-        game_id: created_game.id,
-        owner: ctx.sender,
-        x: 0,
-        y: 0,
-        hp: 100,
-        movement_left: 3,
-        has_attacked: false,
-    };
-    Unit::insert(unit1);
+    match ViewKey::filter_by_player(&ctx.sender) {
+        // Nonces keep counting up, so a key set again later is still safe
+        Some(old) => {
+            ViewKey::update_by_player(&ctx.sender, ViewKey { key, ..old });
+        }
+        None => {
+            ViewKey::insert(ViewKey { player: ctx.sender, key, next_nonce: 0 }).unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Create a new game session - player becomes player1
+#[spacetimedb(reducer)]
+pub fn create_game(ctx: ReducerContext, settings: LobbySettings) -> Result<(), GameError> {
+    // Check if player exists and is free to play
+    let player = Player::filter_by_id(&ctx.sender).ok_or(GameError::PlayerNotFound)?;
+    check_not_in_game(&player)?;
     
-    log::info!("Game created: {}", created_game.id);
+    // The new id shows up in the player's `current_game`
+    start_game(ctx.sender, &settings)?;
+    Ok(())
 }
 
-/// Join an existing game as player2
+/// Change the settings of a game while its host is the only one seated.
+/// The board is set up again from scratch.
 #[spacetimedb(reducer)]
-pub fn join_game(ctx: ReducerContext, game_id: u32) {
-    // Check if player exists
-    if Player::filter_by_id(&ctx.sender).is_none() {
-        log::error!("Player not found: {:?}", ctx.sender);
-        return;
+pub fn update_lobby(ctx: ReducerContext, game_id: u32, settings: LobbySettings) -> Result<(), GameError> {
+    let mut game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
+    if game.player1 != ctx.sender {
+        return Err(GameError::NotHost);
     }
-    
-    // Get game
-    let mut game = match Game::filter_by_id(&game_id) {
-        Some(g) => g,
-        None => {
-            log::error!("Game not found: {}", game_id);
-            return;
-        }
-    };
-    
-    // Check if game is waiting for players
     if game.status != GameStatus::WaitingForPlayers {
-        log::error!("Game not accepting players: {}", game_id);
-        return;
+        return Err(GameError::GameNotJoinable);
     }
-    
-    // Check if player is already in game
-    if game.player1 == ctx.sender {
-        log::error!("Player already in game: {:?}", ctx.sender);
-        return;
+    if seated_players(game_id).len() > 1 {
+        return Err(GameError::LobbyHasPlayers);
     }
+    let (squad_size, turn_time_limit, spectator_delay) = validate_settings(&settings)?;
     
-    // Join as player 2
-    game.player2 = Some(ctx.sender);
-    game.status = GameStatus::InProgress;
+    game.squad_size = squad_size;
+    game.turn_time_limit = turn_time_limit;
+    game.mode = settings.mode;
+    game.team_points = vec![0; team_count(&settings.mode)];
+    clear_board(game_id);
+    let seats = seat_teams(&settings.mode).len();
+    let board = resolve_map(game_id, &settings.map, board_seed(game_id, settings.seed), seats).unwrap();
+    set_up_board(&game, board);
+    set_visibility(&mut game, &settings.visibility);
+    game.spectator_delay = spectator_delay;
     Game::update_by_id(&game_id, game);
+    refresh_spectator_view(game_id);
     
-    // Create player 2's unit at opposite corner (4,4)
-    let unit2 = Unit {
-        id: 0, // autoinc
-        game_id: game_id,
-        owner: ctx.sender,
-        x: 4,
-        y: 4,
-        hp: 100,
-        movement_left: 3,
-        has_attacked: false,
-    };
-    Unit::insert(unit2);
+    log::info!("Lobby {} updated", game_id);
+    Ok(())
+}
+
+/// Join an existing game in its next open seat
+#[spacetimedb(reducer)]
+pub fn join_game(ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
+    check_can_join(&game, ctx.sender)?;
+    seat_player(game, ctx.sender);
+    Ok(())
+}
+
+/// Leave a game that is still waiting for players. When the host leaves,
+/// the lobby is closed for everyone in it.
+#[spacetimedb(reducer)]
+pub fn leave_lobby(ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    if game.status != GameStatus::WaitingForPlayers {
+        return Err(GameError::GameNotJoinable);
+    }
+    if seat == 0 {
+        close_lobby(game);
+    } else {
+        free_seat(game, seat);
+    }
+    Ok(())
+}
+
+/// Set the invite code of your private game, at least
+/// `MIN_INVITE_CODE_LENGTH` characters. Only its hash is kept; share the code
+/// with your friends yourself. Setting it again replaces the old code.
+///
+/// Reducer arguments reach every client subscribed to a row the call
+/// changes, so this only writes private tables.
+#[spacetimedb(reducer)]
+pub fn set_invite_code(ctx: ReducerContext, game_id: u32, code: String) -> Result<(), GameError> {
+    let game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
+    if game.player1 != ctx.sender {
+        return Err(GameError::NotHost);
+    }
+    if game.status != GameStatus::WaitingForPlayers {
+        return Err(GameError::GameNotJoinable);
+    }
+    if !game.private {
+        return Err(GameError::GameNotPrivate);
+    }
+    if code.trim().chars().count() < MIN_INVITE_CODE_LENGTH {
+        return Err(GameError::InviteCodeTooShort(MIN_INVITE_CODE_LENGTH));
+    }
     
-    log::info!("Player {:?} joined game {}", ctx.sender, game_id);
+    remove_invite(game_id);
+    let code_hash = invite_code_hash(game_id, &code);
+    GameInvite::insert(GameInvite { game_id, code_hash, host: ctx.sender }).unwrap();
+    Ok(())
 }
 
-/// Move a unit to a new position (costs movement points)
+/// Hand in the invite code of a private game, then take the seat with
+/// `join_game`. Two steps for the same reason as `set_invite_code`: joining
+/// changes public rows, which would pass the code on to other clients.
 #[spacetimedb(reducer)]
-pub fn move_unit(ctx: ReducerContext, game_id: u32, unit_id: u32, new_x: u8, new_y: u8) {
-    // Validate game and turn
-    let game = match Game::filter_by_id(&game_id) {
-        Some(g) => g,
+pub fn use_invite_code(ctx: ReducerContext, game_id: u32, code: String) -> Result<(), GameError> {
+    let invite = GameInvite::filter_by_game_id(&game_id)
+        .filter(|invite| invite.code_hash == invite_code_hash(game_id, &code))
+        .ok_or(GameError::InvalidInviteCode)?;
+    let game = Game::filter_by_id(&invite.game_id).ok_or(GameError::GameNotFound(invite.game_id))?;
+    if InvitePass::filter_by_game_id(&game_id).any(|pass| pass.player == ctx.sender) {
+        return Ok(());
+    }
+    check_can_join(&game, ctx.sender).or_else(|err| match err {
+        GameError::InviteRequired => Ok(()),
+        err => Err(err),
+    })?;
+    InvitePass::insert(InvitePass { id: 0, game_id, player: ctx.sender }).unwrap();
+    Ok(())
+}
+
+/// Lock in your army once every seat is taken. `units` must fit the budget
+/// for the game's squad size and sit in your corner's deployment zone. The
+/// battle starts when every player has locked in; until then nobody sees
+/// anyone else's army.
+#[spacetimedb(reducer)]
+pub fn deploy(ctx: ReducerContext, game_id: u32, units: Vec<UnitPlacement>) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    if game.status != GameStatus::Deployment {
+        return Err(GameError::NotDeploying);
+    }
+    let board = Board::filter_by_game_id(&game_id).unwrap();
+    let terrain = deserialize_board(&board.terrain_data);
+    validate_deployment(&terrain, seat, deployment_budget(game.squad_size), &units)?;
+    lock_deployment(game_id, ctx.sender, units)?;
+    // The army is only in the arguments of this call, so `deployed` and the
+    // battle start are left to `publish_games`
+    queue_publish(game_id);
+    Ok(())
+}
+
+/// Ask for a rematch of a finished game. Once the opponent asks too, a new
+/// game starts on the same board and settings with the starting sides
+/// swapped. The new id shows up in both players' `current_game`. Duels only.
+#[spacetimedb(reducer)]
+pub fn request_rematch(ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let (game, _) = load_game(game_id, ctx.sender)?;
+    if game.status != GameStatus::Finished {
+        return Err(GameError::GameNotFinished);
+    }
+    if game.mode != GameMode::Duel {
+        return Err(GameError::DuelOnly);
+    }
+    if Game::iter().any(|g| g.rematch_of == Some(game_id)) {
+        return Err(GameError::RematchAlreadyRequested);
+    }
+    
+    match RematchRequest::filter_by_game_id(&game_id) {
         None => {
-            log::error!("Game not found: {}", game_id);
-            return;
+            RematchRequest::insert(RematchRequest {
+                game_id,
+                requested_by: ctx.sender,
+                created_at: timestamp(),
+            }).unwrap();
+            log::info!("Player {:?} asked for a rematch of game {}", ctx.sender, game_id);
+            return Ok(());
+        }
+        Some(request) if request.requested_by == ctx.sender => return Err(GameError::RematchAlreadyRequested),
+        Some(_) => {}
+    }
+    // Neither player may have moved on to another game in the meantime
+    for id in [game.player1, game.player2.unwrap()] {
+        if let Some(player) = Player::filter_by_id(&id) {
+            check_not_in_game(&player)?;
         }
-    };
-    
-    if game.status != GameStatus::InProgress {
-        log::error!("Game not in progress: {}", game_id);
-        return;
     }
+    RematchRequest::delete_by_game_id(&game_id);
     
-    if game.current_turn != ctx.sender {
-        log::error!("Not player's turn: {:?}", ctx.sender);
-        return;
+    // The old player 2 starts this time
+    let old_board = Board::filter_by_game_id(&game_id).unwrap();
+    let mut rematch = create_game_row(game.player2.unwrap(), game.mode, game.squad_size, game.turn_time_limit);
+    let rematch_id = rematch.id;
+    set_up_board(&rematch, Board { game_id: rematch_id, ..old_board });
+    rematch.private = game.private;
+    rematch.spectator_delay = game.spectator_delay;
+    rematch.rematch_of = Some(game_id);
+    seat_player(rematch, game.player1);
+    
+    log::info!("Rematch of game {} started as game {}", game_id, rematch_id);
+    Ok(())
+}
+
+/// Join the matchmaking queue. If anyone is already waiting, the closest
+/// rating is paired straight away into a new game; the longer-waiting player
+/// is player 1. Otherwise the caller waits for the next `enqueue`.
+#[spacetimedb(reducer)]
+pub fn enqueue(ctx: ReducerContext) -> Result<(), GameError> {
+    let player = Player::filter_by_id(&ctx.sender).ok_or(GameError::PlayerNotFound)?;
+    if MatchmakingQueue::filter_by_player(&ctx.sender).is_some() {
+        return Err(GameError::AlreadyQueued);
     }
+    check_not_in_game(&player)?;
     
-    // Get unit
-    let mut unit = match Unit::filter_by_id(&unit_id) {
-        Some(u) => u,
+    let waiting: Vec<MatchmakingQueue> = MatchmakingQueue::iter().collect();
+    let candidates: Vec<(i32, u64)> = waiting.iter().map(|q| (q.rating, q.joined_at)).collect();
+    let opponent = match closest_match(player.rating, &candidates) {
+        Some(i) => &waiting[i],
         None => {
-            log::error!("Unit not found: {}", unit_id);
-            return;
+            MatchmakingQueue::insert(MatchmakingQueue {
+                player: ctx.sender,
+                rating: player.rating,
+                joined_at: timestamp(),
+            }).unwrap();
+            log::info!("Player {:?} queued at rating {}", ctx.sender, player.rating);
+            return Ok(());
         }
     };
     
-    if unit.owner != ctx.sender {
-        log::error!("Unit not owned by player: {}", unit_id);
-        return;
+    log::info!("Matched {:?} ({}) with {:?} ({})", opponent.player, opponent.rating, ctx.sender, player.rating);
+    let game_id = start_game(opponent.player, &matchmaking_settings())?;
+    seat_player(Game::filter_by_id(&game_id).unwrap(), ctx.sender);
+    Ok(())
+}
+
+/// Create a tournament. Players register with `register_for_tournament`
+/// until the organizer calls `start_tournament`.
+#[spacetimedb(reducer)]
+pub fn create_tournament(ctx: ReducerContext, name: String, format: TournamentFormat, settings: LobbySettings) -> Result<(), GameError> {
+    if Player::filter_by_id(&ctx.sender).is_none() {
+        return Err(GameError::PlayerNotFound);
     }
+    validate_settings(&settings)?;
     
-    if unit.game_id != game_id {
-        log::error!("Unit not in this game: {}", unit_id);
-        return;
+    // Players are seated directly, so tournament games don't need invites,
+    // and matches are always one against one
+    let settings = LobbySettings { visibility: LobbyVisibility::Public, mode: GameMode::Duel, ..settings };
+    let tournament = Tournament::insert(Tournament {
+        id: 0, // autoinc
+        name,
+        organizer: ctx.sender,
+        format,
+        settings,
+        status: TournamentStatus::Registration,
+        current_round: 0,
+        total_rounds: 0,
+        winner: None,
+        created_at: timestamp(),
+    })
+    .unwrap();
+    log::info!("Tournament created: {}", tournament.id);
+    Ok(())
+}
+
+/// Sign up for a tournament that hasn't started yet
+#[spacetimedb(reducer)]
+pub fn register_for_tournament(ctx: ReducerContext, tournament_id: u32) -> Result<(), GameError> {
+    if Player::filter_by_id(&ctx.sender).is_none() {
+        return Err(GameError::PlayerNotFound);
     }
-    
-    // Validate move
-    if new_x >= 5 || new_y >= 5 {
-        log::error!("Invalid position: {},{}", new_x, new_y);
-        return;
+    let tournament = Tournament::filter_by_id(&tournament_id).ok_or(GameError::TournamentNotFound(tournament_id))?;
+    if tournament.status != TournamentStatus::Registration {
+        return Err(GameError::RegistrationClosed);
+    }
+    if TournamentParticipant::iter().any(|p| p.tournament_id == tournament_id && p.player == ctx.sender) {
+        return Err(GameError::AlreadyRegistered);
     }
     
-    let distance = calculate_distance(unit.x, unit.y, new_x, new_y);
-    if distance > unit.movement_left {
-        log::error!("Not enough movement: {} > {}", distance, unit.movement_left);
-        return;
+    TournamentParticipant::insert(TournamentParticipant {
+        id: 0, // autoinc
+        tournament_id,
+        player: ctx.sender,
+        seed: 0,
+        score: 0,
+        eliminated: false,
+        had_bye: false,
+        registered_at: timestamp(),
+    }).unwrap();
+    log::info!("Player {:?} registered for tournament {}", ctx.sender, tournament_id);
+    Ok(())
+}
+
+/// Drop out of a tournament before it starts
+#[spacetimedb(reducer)]
+pub fn leave_tournament(ctx: ReducerContext, tournament_id: u32) -> Result<(), GameError> {
+    let tournament = Tournament::filter_by_id(&tournament_id).ok_or(GameError::TournamentNotFound(tournament_id))?;
+    if tournament.status != TournamentStatus::Registration {
+        return Err(GameError::RegistrationClosed);
+    }
+    let participant = TournamentParticipant::iter()
+        .find(|p| p.tournament_id == tournament_id && p.player == ctx.sender)
+        .ok_or(GameError::NotRegistered)?;
+    TournamentParticipant::delete_by_id(&participant.id);
+    log::info!("Player {:?} left tournament {}", ctx.sender, tournament_id);
+    Ok(())
+}
+
+/// Close registration, seed the players by rating and start round 1.
+/// Organizer only. Later rounds start by themselves as games finish.
+#[spacetimedb(reducer)]
+pub fn start_tournament(ctx: ReducerContext, tournament_id: u32) -> Result<(), GameError> {
+    let mut tournament = Tournament::filter_by_id(&tournament_id).ok_or(GameError::TournamentNotFound(tournament_id))?;
+    if tournament.organizer != ctx.sender {
+        return Err(GameError::NotOrganizer);
+    }
+    if tournament.status != TournamentStatus::Registration {
+        return Err(GameError::RegistrationClosed);
+    }
+    let mut participants: Vec<TournamentParticipant> =
+        TournamentParticipant::iter().filter(|p| p.tournament_id == tournament_id).collect();
+    if participants.len() < MIN_TOURNAMENT_PLAYERS {
+        return Err(GameError::NotEnoughPlayers(MIN_TOURNAMENT_PLAYERS));
     }
     
-    // Check if destination is occupied
-    if let Some(_) = Unit::iter()
-        .find(|u| u.game_id == game_id && u.x == new_x && u.y == new_y && u.hp > 0) {
-        log::error!("Destination occupied: {},{}", new_x, new_y);
-        return;
+    participants.sort_by_key(|p| p.registered_at);
+    let ratings: Vec<i32> = participants
+        .iter()
+        .map(|p| Player::filter_by_id(&p.player).map_or(STARTING_RATING, |player| player.rating))
+        .collect();
+    for (seed, i) in seed_by_rating(&ratings).into_iter().enumerate() {
+        let mut participant = TournamentParticipant::filter_by_id(&participants[i].id).unwrap();
+        participant.seed = seed as u32;
+        TournamentParticipant::update_by_id(&participants[i].id, participant);
     }
     
-    // Move unit
-    unit.x = new_x;
-    unit.y = new_y;
-    unit.movement_left -= distance;
-    Unit::update_by_id(&unit_id, unit);
+    tournament.status = TournamentStatus::InProgress;
+    tournament.current_round = 1;
+    tournament.total_rounds = round_count(participants.len());
+    start_round(&tournament, 1)?;
+    Tournament::update_by_id(&tournament_id, tournament);
+    log::info!("Tournament {} started with {} players", tournament_id, participants.len());
+    Ok(())
+}
+
+/// Seat a bot in the next open seat of your waiting game, for single-player
+/// games or to fill out a free-for-all or team game
+#[spacetimedb(reducer)]
+pub fn add_bot(ctx: ReducerContext, game_id: u32, difficulty: BotDifficulty) -> Result<(), GameError> {
+    let game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
+    if game.player1 != ctx.sender {
+        return Err(GameError::NotHost);
+    }
+    if game.status != GameStatus::WaitingForPlayers {
+        return Err(GameError::GameNotJoinable);
+    }
+    seat_bot(game, difficulty);
+    log::info!("{:?} bot joined game {}", difficulty, game_id);
+    Ok(())
+}
+
+/// Start a game between bots, for soak testing. Admin only. The first seat
+/// plays at `difficulty1` and every other seat at `difficulty2`. Anyone can
+/// watch it.
+#[spacetimedb(reducer)]
+pub fn start_bot_match(
+    ctx: ReducerContext,
+    settings: LobbySettings,
+    difficulty1: BotDifficulty,
+    difficulty2: BotDifficulty,
+) -> Result<(), GameError> {
+    check_admin(ctx.sender)?;
+    let bot = bot_identity(difficulty1, 0);
+    let game_id = start_game(bot, &settings)?;
+    GameBot::insert(GameBot { id: 0, game_id, bot, difficulty: difficulty1 }).unwrap();
+    let game = Game::filter_by_id(&game_id).unwrap();
+    let board = Board::filter_by_game_id(&game_id).unwrap();
+    lock_deployment(game_id, bot, default_deployment(board.width, board.height, game.squad_size, 0))?;
+    for _ in 1..seat_teams(&settings.mode).len() {
+        seat_bot(Game::filter_by_id(&game_id).unwrap(), difficulty2);
+    }
+    log::info!("Bot match {} started: {:?} vs {:?}", game_id, difficulty1, difficulty2);
+    Ok(())
+}
+
+/// Leave the matchmaking queue
+#[spacetimedb(reducer)]
+pub fn dequeue(ctx: ReducerContext) -> Result<(), GameError> {
+    if MatchmakingQueue::filter_by_player(&ctx.sender).is_none() {
+        return Err(GameError::NotQueued);
+    }
+    MatchmakingQueue::delete_by_player(&ctx.sender);
+    log::info!("Player {:?} left the queue", ctx.sender);
+    Ok(())
+}
+
+/// Move a unit to a new position (costs movement points)
+#[spacetimedb(reducer)]
+pub fn move_unit(ctx: ReducerContext, game_id: u32, unit_id: u32, new_x: u8, new_y: u8) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    let turn_number = game.turn_number;
+    let mut state = load_state(&game);
     
-    // Log action
-    let action = GameAction {
-        id: 0, // autoinc
-        game_id,
-        player: ctx.sender,
-        action_type: "move".to_string(),
-        details: format!("{{\"unit_id\":{},\"to\":[{},{}]}}", unit_id, new_x, new_y),
-        timestamp: spacetimedb::timestamp(),
-    };
-    GameAction::insert(action);
+    let action = state.move_unit(seat, unit_id, Position { x: new_x, y: new_y })?;
+    log_action(game_id, turn_number, ctx.sender, ActionPayload::Move(action));
+    save_units(game_id, &seated_players(game_id), &state);
+    queue_publish(game_id);
     
     log::info!("Unit {} moved to {},{}", unit_id, new_x, new_y);
+    Ok(())
 }
 
-/// Attack an adjacent enemy unit
+/// Attack an enemy unit within range. Surviving defenders counter-attack.
 #[spacetimedb(reducer)]
-pub fn attack_unit(ctx: ReducerContext, game_id: u32, attacker_id: u32, target_id: u32) {
-    // Validate game and turn
-    let game = match Game::filter_by_id(&game_id) {
-        Some(g) => g,
-        None => {
-            log::error!("Game not found: {}", game_id);
-            return;
+pub fn attack_unit(ctx: ReducerContext, game_id: u32, attacker_id: u32, target_id: u32) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    let turn_number = game.turn_number;
+    let mut state = load_state(&game);
+    
+    // The game ends once `publish_games` sees either squad wiped out
+    let action = state.attack(seat, attacker_id, target_id)?;
+    log::info!("Unit {} attacked unit {} for {} damage ({} counter)", attacker_id, target_id, action.damage, action.counter_damage);
+    log_action(game_id, turn_number, ctx.sender, ActionPayload::Attack(action));
+    save_units(game_id, &seated_players(game_id), &state);
+    queue_publish(game_id);
+    Ok(())
+}
+
+/// Use one of a unit's abilities. `target_id` is the unit aimed at, or None
+/// for abilities used on the unit itself (Fortify, Dash).
+#[spacetimedb(reducer)]
+pub fn use_ability(ctx: ReducerContext, game_id: u32, unit_id: u32, ability: String, target_id: Option<u32>) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    let definition = AbilityDefinition::filter_by_name(&ability)
+        .filter(|d| d.enabled)
+        .ok_or(GameError::AbilityNotFound(ability.clone()))?;
+    let turn_number = game.turn_number;
+    let mut state = load_state(&game);
+    if let Some(unit) = state.units.iter().find(|u| u.id == unit_id) {
+        if unit.class != definition.class {
+            return Err(GameError::AbilityNotForClass(ability));
         }
+    }
+    
+    // A Shot can end the game like an attack, published the same way
+    let action = state.use_ability(seat, unit_id, &ability_spec(&definition), target_id)?;
+    log::info!("Unit {} used {} ({})", unit_id, ability, action.amount);
+    log_action(game_id, turn_number, ctx.sender, ActionPayload::Ability(action));
+    save_units(game_id, &seated_players(game_id), &state);
+    queue_publish(game_id);
+    Ok(())
+}
+
+/// Compute the expected outcome of an attack on a unit you can see without
+/// committing it. The result shows up in your `SealedView` once
+/// `publish_games` has run.
+#[spacetimedb(reducer)]
+pub fn preview_attack(ctx: ReducerContext, game_id: u32, attacker_id: u32, target_id: u32) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    let outcome = load_state(&game).preview_attack(seat, attacker_id, target_id)?;
+    
+    CombatPreview::delete_by_player(&ctx.sender);
+    let preview = AttackPreview {
+        attacker_id,
+        target_id,
+        damage: outcome.damage,
+        flanking: outcome.flanking,
+        counter_damage: outcome.counter_damage,
+        range: outcome.range,
+        obstruction: outcome.obstruction.map(|(x, y)| Position { x, y }),
+        target_hp_after: outcome.target_hp_after,
+        attacker_hp_after: outcome.attacker_hp_after,
     };
+    CombatPreview::insert(CombatPreview { player: ctx.sender, game_id, preview, created_at: timestamp() }).unwrap();
+    queue_publish(game_id);
+    Ok(())
+}
+
+/// End current player's turn and pass it to the next player still in the
+/// game. Scores capture points, checks the victory point and round limits,
+/// then heals and resets the next player's units.
+#[spacetimedb(reducer)]
+pub fn end_turn(ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    let turn_number = game.turn_number;
+    let mut state = load_state(&game);
     
-    if game.status != GameStatus::InProgress {
-        log::error!("Game not in progress: {}", game_id);
-        return;
+    state.end_turn(seat)?;
+    // Ending a turn in time clears the player's timeout streak
+    if let Some(mut participant) =
+        GameParticipant::iter().find(|p| p.game_id == game_id && p.player == ctx.sender && p.timeouts > 0)
+    {
+        let id = participant.id;
+        participant.timeouts = 0;
+        GameParticipant::update_by_id(&id, participant);
     }
+    let next_player = player_in_seat(&game, state.current_turn);
+    log_action(game_id, turn_number, ctx.sender, ActionPayload::EndTurn);
+    save_state(game, &state)?;
+    refresh_visibility(game_id);
     
-    if game.current_turn != ctx.sender {
-        log::error!("Not player's turn: {:?}", ctx.sender);
-        return;
-    }
+    log::info!("Turn ended. Now {:?}'s turn", next_player);
+    Ok(())
+}
+
+/// Give up a game in progress: your units leave play. In a duel the opponent
+/// wins; otherwise the game goes on without you until one team is left. Can
+/// be called on any player's turn.
+#[spacetimedb(reducer)]
+pub fn surrender(ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let (game, seat) = load_game(game_id, ctx.sender)?;
+    let turn_number = game.turn_number;
+    let mut state = load_state(&game);
     
-    // Get units
-    let mut attacker = match Unit::filter_by_id(&attacker_id) {
-        Some(u) => u,
-        None => {
-            log::error!("Attacker not found: {}", attacker_id);
-            return;
-        }
-    };
+    state.surrender(seat)?;
+    log_action(game_id, turn_number, ctx.sender, ActionPayload::Surrender);
+    save_state(game, &state)?;
+    refresh_visibility(game_id);
     
-    let mut target = match Unit::filter_by_id(&target_id) {
-        Some(u) => u,
-        None => {
-            log::error!("Target not found: {}", target_id);
-            return;
+    log::info!("Player {:?} surrendered game {}", ctx.sender, game_id);
+    Ok(())
+}
+
+/// Publish what moves, attacks, abilities, previews and deployments changed:
+/// every player's `SealedView`, the spectators' view, who has deployed and,
+/// when an attack decided it, the eliminations and the end of the game.
+/// Scheduled by those reducers with no arguments, so the rows it changes
+/// reach subscribers without any of theirs.
+#[spacetimedb(reducer)]
+pub fn publish_games(_ctx: ReducerContext) {
+    for pending in PendingPublish::iter().collect::<Vec<_>>() {
+        PendingPublish::delete_by_game_id(&pending.game_id);
+        let game = match Game::filter_by_id(&pending.game_id) {
+            Some(g) => g,
+            None => continue,
+        };
+        match game.status {
+            GameStatus::Deployment => start_battle_if_ready(pending.game_id),
+            GameStatus::InProgress => {
+                let state = load_state(&game);
+                if let Err(e) = save_state(game, &state) {
+                    log::error!("Could not publish game {}: {}", pending.game_id, e);
+                }
+                refresh_visibility(pending.game_id);
+            }
+            _ => refresh_visibility(pending.game_id),
         }
-    };
-    
-    // Validate attack
-    if attacker.owner != ctx.sender {
-        log::error!("Attacker not owned by player: {}", attacker_id);
-        return;
+    }
+}
+
+/// Whether a reducer was started by the module's own schedule
+fn from_schedule(ctx: &ReducerContext) -> bool {
+    match (ModuleAddress::filter_by_id(&0), ctx.address) {
+        (Some(module), Some(address)) => module.address == address,
+        _ => false,
+    }
+}
+
+/// End every turn and deployment phase whose deadline has passed. Runs every
+/// second; calling it by hand does no more than the schedule would, and
+/// doesn't start a second schedule.
+#[spacetimedb(reducer)]
+pub fn check_turn_timers(ctx: ReducerContext) {
+    // Scheduled before anything that can fail, so one bad game can't stop the timers
+    if from_schedule(&ctx) {
+        spacetimedb::schedule!("1s", check_turn_timers(_));
     }
     
-    if attacker.game_id != game_id || target.game_id != game_id {
-        log::error!("Units not in this game");
-        return;
+    let now = timestamp();
+    let overdue: Vec<Game> = Game::iter()
+        .filter(|g| matches!(g.status, GameStatus::Deployment | GameStatus::InProgress) && g.turn_deadline <= now)
+        .collect();
+    for game in overdue {
+        if game.status == GameStatus::Deployment {
+            time_out_deployment(game);
+        } else {
+            let game_id = game.id;
+            if let Err(e) = time_out_turn(game) {
+                log::error!("Could not time out game {}: {}", game_id, e);
+            }
+        }
+    }
+}
+
+/// Scheduled every second: play the turn of every bot whose turn it is,
+/// clear out bots of finished games, and give players who have waited in
+/// the matchmaking queue too long a bot opponent
+#[spacetimedb(reducer)]
+pub fn run_bots(ctx: ReducerContext) {
+    // Scheduled first for the same reason as `check_turn_timers`
+    if from_schedule(&ctx) {
+        spacetimedb::schedule!("1s", run_bots(_));
     }
     
-    if attacker.has_attacked {
-        log::error!("Unit already attacked: {}", attacker_id);
-        return;
+    for bot in GameBot::iter().collect::<Vec<_>>() {
+        match Game::filter_by_id(&bot.game_id) {
+            Some(game) if game.status == GameStatus::Finished => {
+                GameBot::delete_by_id(&bot.id);
+            }
+            Some(game) if game.status == GameStatus::InProgress && game.current_turn == bot.bot => {
+                if let Err(e) = play_bot_turn(bot.game_id, bot.bot, bot.difficulty) {
+                    log::error!("Bot turn failed in game {}: {}", bot.game_id, e);
+                }
+            }
+            Some(_) => {}
+            None => {
+                GameBot::delete_by_id(&bot.id);
+            }
+        }
     }
     
-    if target.hp <= 0 {
-        log::error!("Target already dead: {}", target_id);
-        return;
+    let now = timestamp();
+    let waited_out: Vec<MatchmakingQueue> = MatchmakingQueue::iter()
+        .filter(|q| q.joined_at + BOT_FILL_WAIT_SECS * 1000 <= now)
+        .collect();
+    for queued in waited_out {
+        let game_id = match start_game(queued.player, &matchmaking_settings()) {
+            Ok(id) => id,
+            Err(e) => {
+                log::error!("Could not start a bot game for {:?}: {}", queued.player, e);
+                continue;
+            }
+        };
+        seat_bot(Game::filter_by_id(&game_id).unwrap(), BOT_FILL_DIFFICULTY);
+        log::info!("Player {:?} matched with a bot after waiting {}s", queued.player, BOT_FILL_WAIT_SECS);
     }
+}
+
+/// Rebuild a finished game from its action log into `ReplayFrame` rows and
+/// record in `Replay` whether the rules reproduce the game exactly
+#[spacetimedb(reducer)]
+pub fn build_replay(_ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
     
-    if !is_adjacent(attacker.x, attacker.y, target.x, target.y) {
-        log::error!("Target not adjacent");
-        return;
+    if game.status != GameStatus::Finished {
+        return Err(GameError::GameNotFinished);
     }
     
-    // Get terrain defense
     let board = Board::filter_by_game_id(&game_id).unwrap();
-    let terrain_map = deserialize_board(&board.terrain_data);
-    let target_terrain = terrain_map[target.y as usize][target.x as usize];
-    let defense = get_terrain_defense(target_terrain);
     
-    // Calculate damage
-    let base_damage = 30;
-    let final_damage = base_damage - (defense * 10);
+    // Start from a clean timeline
+    for frame in ReplayFrame::iter().filter(|f| f.game_id == game_id).collect::<Vec<_>>() {
+        ReplayFrame::delete_by_id(&frame.id);
+    }
+    Replay::delete_by_game_id(&game_id);
     
-    // Apply damage
-    target.hp -= final_damage;
-    if target.hp < 0 {
-        target.hp = 0;
+    let (frames, result) = replay_game(&game, &board);
+    let divergence = result.and_then(|state| check_final_state(&game, &state)).err();
+    if let Some(e) = &divergence {
+        log::error!("Replay of game {} diverged: {}", game_id, e);
     }
-    Unit::update_by_id(&target_id, target);
     
-    // Mark attacker as having attacked
-    attacker.has_attacked = true;
-    Unit::update_by_id(&attacker_id, attacker);
+    Replay::insert(Replay {
+        game_id,
+        frames,
+        verified: divergence.is_none(),
+        divergence,
+        built_at: timestamp(),
+    }).unwrap();
+    log::info!("Replay built for game {}: {} frames", game_id, frames);
+    Ok(())
+}
+
+/// Watch a game you aren't playing in. `Player(identity)` perspectives only
+/// show what that player could see.
+#[spacetimedb(reducer)]
+pub fn watch_game(ctx: ReducerContext, game_id: u32, perspective: SpectatorPerspective) -> Result<(), GameError> {
+    let game = Game::filter_by_id(&game_id).ok_or(GameError::GameNotFound(game_id))?;
+    if seat_of(&game, ctx.sender).is_some() {
+        return Err(GameError::PlayerCannotSpectate);
+    }
+    if let SpectatorPerspective::Player(player) = perspective {
+        if seat_of(&game, player).is_none() {
+            return Err(GameError::NotInGame);
+        }
+    }
+    if Spectator::iter().any(|s| s.game_id == game_id && s.spectator == ctx.sender) {
+        return Err(GameError::AlreadyWatching);
+    }
     
-    // Log action
-    let action = GameAction {
+    Spectator::insert(Spectator {
         id: 0, // autoinc
         game_id,
-        player: ctx.sender,
-        action_type: "attack".to_string(),
-        details: format!("{{\"attacker_id\":{},\"target_id\":{},\"damage\":{}}}", 
-                        attacker_id, target_id, final_damage),
-        timestamp: spacetimedb::timestamp(),
-    };
-    GameAction::insert(action);
-    
-    // Check for victory
-    if target.hp == 0 {
-        let mut game_update = game;
-        game_update.status = GameStatus::Finished;
-        game_update.winner = Some(ctx.sender);
-        Game::update_by_id(&game_id, game_update);
-        log::info!("Game {} won by {:?}", game_id, ctx.sender);
+        spectator: ctx.sender,
+        perspective,
+        joined_at: timestamp(),
+    }).unwrap();
+    refresh_spectator_view(game_id);
+    log::info!("{:?} is watching game {}", ctx.sender, game_id);
+    Ok(())
+}
+
+/// Stop watching a game
+#[spacetimedb(reducer)]
+pub fn stop_watching(ctx: ReducerContext, game_id: u32) -> Result<(), GameError> {
+    let spectator = Spectator::iter()
+        .find(|s| s.game_id == game_id && s.spectator == ctx.sender)
+        .ok_or(GameError::NotWatching)?;
+    Spectator::delete_by_id(&spectator.id);
+    refresh_spectator_view(game_id);
+    Ok(())
+}
+
+/// Add or rebalance an ability. Admin only; takes effect from the next use.
+#[spacetimedb(reducer)]
+pub fn set_ability(ctx: ReducerContext, class: UnitClass, spec: AbilitySpec, enabled: bool) -> Result<(), GameError> {
+    check_admin(ctx.sender)?;
+    validate_ability(&spec).map_err(GameError::InvalidAbility)?;
+    log::info!("Ability {} set: {:?}", spec.name, spec);
+    put_ability(class, spec, enabled);
+    Ok(())
+}
+
+/// Let another identity change game data. Admin only.
+#[spacetimedb(reducer)]
+pub fn add_admin(ctx: ReducerContext, identity: Identity) -> Result<(), GameError> {
+    check_admin(ctx.sender)?;
+    if Admin::filter_by_identity(&identity).is_none() {
+        Admin::insert(Admin { identity, added_at: timestamp() }).unwrap();
     }
-    
-    log::info!("Unit {} attacked unit {} for {} damage", attacker_id, target_id, final_damage);
+    Ok(())
 }
 
-/// End current player's turn and switch to opponent
+/// Save a hand-made map so games can be created on it by name
 #[spacetimedb(reducer)]
-pub fn end_turn(ctx: ReducerContext, game_id: u32) {
-    // Get game
-    let mut game = match Game::filter_by_id(&game_id) {
-        Some(g) => g,
-        None => {
-            log::error!("Game not found: {}", game_id);
-            return;
-        }
-    };
-    
-    if game.status != GameStatus::InProgress {
-        log::error!("Game not in progress: {}", game_id);
-        return;
+pub fn create_map_preset(ctx: ReducerContext, name: String, width: u8, height: u8, tiles: String, objectives: Option<String>) -> Result<(), GameError> {
+    if Player::filter_by_id(&ctx.sender).is_none() {
+        return Err(GameError::PlayerNotFound);
     }
     
-    if game.current_turn != ctx.sender {
-        log::error!("Not player's turn: {:?}", ctx.sender);
-        return;
+    if MapPreset::filter_by_name(&name).is_some() {
+        return Err(GameError::MapPresetExists(name));
     }
     
-    // Switch turn
-    let player2 = game.player2.unwrap();
-    game.current_turn = if game.current_turn == game.player1 {
-        player2
-    } else {
-        game.player1
-    };
-    Game::update_by_id(&game_id, game);
+    validate_map_tiles(width, height, &tiles).map_err(GameError::InvalidMap)?;
     
-    // Reset units for next player
-    for mut unit in Unit::iter().filter(|u| u.game_id == game_id && u.owner == game.current_turn) {
-        unit.movement_left = 3;
-        unit.has_attacked = false;
-        Unit::update_by_id(&unit.id, unit);
+    // Without an explicit layout each game on the preset gets the standard
+    // objectives for its seat count
+    if let Some(objectives) = &objectives {
+        validate_map_objectives(width, height, objectives).map_err(GameError::InvalidMap)?;
     }
     
-    log::info!("Turn ended. Now {:?}'s turn", game.current_turn);
+    let preset = MapPreset {
+        name: name.clone(),
+        width,
+        height,
+        terrain_data: serialize_board(&tiles_to_board(width, &tiles)),
+        objective_data: objectives,
+        created_by: Some(ctx.sender),
+        created_at: timestamp(),
+    };
+    MapPreset::insert(preset).unwrap();
+    log::info!("Map preset created: {}", name);
+    Ok(())
 }
 
 #[spacetimedb(init)]
-pub fn init() {
+pub fn init(ctx: ReducerContext) {
+    // Whoever publishes the module can edit game data
+    Admin::insert(Admin { identity: ctx.sender, added_at: timestamp() }).unwrap();
+    
+    // Start the once-a-second reducers
+    if let Some(address) = ctx.address {
+        ModuleAddress::insert(ModuleAddress { id: 0, address }).unwrap();
+    }
+    spacetimedb::schedule!("1s", check_turn_timers(_));
+    spacetimedb::schedule!("1s", run_bots(_));
+    for (class, ability) in default_abilities() {
+        put_ability(class, ability, true);
+    }
+    
+    // Built-in presets
+    let classic = MapPreset {
+        name: "classic".to_string(),
+        width: 5,
+        height: 5,
+        terrain_data: serialize_board(&classic_board(5, 5)),
+        objective_data: None,
+        created_by: None,
+        created_at: timestamp(),
+    };
+    MapPreset::insert(classic).unwrap();
+    
+    let crossroads_tiles = concat!(
+        "PPFPPPP",
+        "PMPPFPP",
+        "FPPFPPP",
+        "PPFMFPP",
+        "PPPFPPF",
+        "PPFPPMP",
+        "PPPPFPP",
+    );
+    let crossroads = MapPreset {
+        name: "crossroads".to_string(),
+        width: 7,
+        height: 7,
+        terrain_data: serialize_board(&tiles_to_board(7, crossroads_tiles)),
+        objective_data: None,
+        created_by: None,
+        created_at: timestamp(),
+    };
+    MapPreset::insert(crossroads).unwrap();
+    
+    let river_crossing_tiles = concat!(
+        "PPPFRPPPP",
+        "PPPURPFPP",
+        "PPFPRPPMP",
+        "PPMPRPUPP",
+        "W~~~R~~~W",
+        "PPUPRPMPP",
+        "PMPPRPFPP",
+        "PPFPRUPPP",
+        "PPPPRFPPP",
+    );
+    let river_crossing = MapPreset {
+        name: "river_crossing".to_string(),
+        width: 9,
+        height: 9,
+        terrain_data: serialize_board(&tiles_to_board(9, river_crossing_tiles)),
+        objective_data: None,
+        created_by: None,
+        created_at: timestamp(),
+    };
+    MapPreset::insert(river_crossing).unwrap();
+    
     log::info!("Game module initialized");
-}
\ No newline at end of file
+}
+
+/// Milliseconds since the Unix epoch, the unit every `*_at` column uses
+fn timestamp() -> u64 {
+    Timestamp::now().into_micros_since_epoch() / 1000
+}
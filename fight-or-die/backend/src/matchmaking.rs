@@ -0,0 +1,51 @@
+//! Skill ratings and opponent selection for the matchmaking queue.
+
+/// Rating every new player starts on
+pub const STARTING_RATING: i32 = 1200;
+
+/// Most rating points a single game can move
+pub const ELO_K_FACTOR: f64 = 32.0;
+
+/// Rating change for a player who scored `score` (1.0 win, 0.5 draw, 0.0 loss)
+/// against `opponent`. The opponent's change is the negation.
+pub fn elo_change(rating: i32, opponent: i32, score: f64) -> i32 {
+    let expected = 1.0 / (1.0 + 10f64.powf((opponent - rating) as f64 / 400.0));
+    (ELO_K_FACTOR * (score - expected)).round() as i32
+}
+
+/// Index of the best opponent among queued `(rating, joined_at)` entries:
+/// the closest rating, then whoever has waited longest
+pub fn closest_match(rating: i32, queued: &[(i32, u64)]) -> Option<usize> {
+    queued
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, &(other, joined_at))| ((other - rating).abs(), joined_at))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn even_match_moves_half_k() {
+        assert_eq!(elo_change(1200, 1200, 1.0), 16);
+        assert_eq!(elo_change(1200, 1200, 0.0), -16);
+        assert_eq!(elo_change(1200, 1200, 0.5), 0);
+    }
+
+    #[test]
+    fn upsets_move_more_than_expected_wins() {
+        let upset = elo_change(1000, 1400, 1.0);
+        let expected = elo_change(1400, 1000, 1.0);
+        assert!(upset > 16 && expected < 16);
+        assert_eq!(upset + elo_change(1400, 1000, 0.0), 0);
+    }
+
+    #[test]
+    fn prefers_closest_rating_then_longest_wait() {
+        assert_eq!(closest_match(1200, &[]), None);
+        assert_eq!(closest_match(1200, &[(1500, 1), (1180, 5), (1250, 2)]), Some(1));
+        assert_eq!(closest_match(1200, &[(1250, 9), (1150, 3)]), Some(1));
+    }
+}
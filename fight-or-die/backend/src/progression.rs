@@ -0,0 +1,324 @@
+//! Career statistics, XP and achievements, worked out from a finished game's
+//! action log.
+
+use crate::error::GameError;
+use crate::rules::{get_class_stats, HEALING_STATION_HP, VICTORY_POINT_TARGET};
+use crate::state::{GameState, Seat, Team, UnitState};
+use crate::{AbilityKind, AchievementKind, ActionPayload, GameStatus, ObjectiveType, TerrainType};
+
+// ===== CONSTANTS =====
+
+/// XP for finishing a game, win or lose
+pub const XP_PER_GAME: u32 = 50;
+/// Extra XP for winning
+pub const XP_PER_WIN: u32 = 100;
+/// Damage dealt per point of bonus XP
+pub const DAMAGE_PER_XP: u32 = 10;
+/// XP needed for level 2. Every level after needs this much more than the last.
+pub const LEVEL_XP_STEP: u32 = 100;
+/// Games a player needs for the Veteran achievement
+pub const VETERAN_GAMES: u32 = 10;
+
+// ===== SUMMARY =====
+
+/// What one player did over a game
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeatSummary {
+    pub damage_dealt: u32,
+    pub damage_taken: u32,
+    pub units_killed: u32,
+    pub units_lost: u32,
+    pub attacks_from: Vec<(TerrainType, u32)>, // Attacks made, by the attacker's tile
+}
+
+impl SeatSummary {
+    fn count_attack_from(&mut self, terrain: TerrainType) {
+        match self.attacks_from.iter_mut().find(|(t, _)| *t == terrain) {
+            Some((_, count)) => *count += 1,
+            None => self.attacks_from.push((terrain, 1)),
+        }
+    }
+}
+
+/// How a game ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Finish {
+    Unfinished,
+    Elimination { by: Seat, from: TerrainType }, // Seat whose attack ended the game, and the attacker's tile
+    VictoryPoints,
+    TurnLimit,
+    Surrender,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSummary {
+    pub seats: Vec<SeatSummary>,
+    pub teams: Vec<Team>, // Team of each seat
+    pub winner: Option<Team>,
+    pub finish: Finish,
+}
+
+impl GameSummary {
+    /// Whether the seat's team won
+    pub fn won(&self, seat: Seat) -> bool {
+        self.winner == Some(self.teams[seat])
+    }
+}
+
+/// HP each unit of the seat whose turn just started lost to status effects,
+/// as (unit id, HP). A Healing Station tops a unit up before poison bites,
+/// so the loss is counted from the topped-up HP.
+fn effect_damage(before: &[UnitState], state: &GameState) -> Vec<(u32, u32)> {
+    let mut lost = Vec::new();
+    for unit in state.units.iter().filter(|u| u.owner == state.current_turn) {
+        let old = match before.iter().find(|b| b.id == unit.id && b.hp > 0) {
+            Some(old) => old,
+            None => continue,
+        };
+        let topped_up = if state.objectives[unit.y as usize][unit.x as usize] == ObjectiveType::HealingStation {
+            (old.hp + HEALING_STATION_HP).min(get_class_stats(unit.class).max_hp)
+        } else {
+            old.hp
+        };
+        if topped_up > unit.hp {
+            lost.push((unit.id, (topped_up - unit.hp) as u32));
+        }
+    }
+    lost
+}
+
+/// Replay a game's actions from its starting state and total up what each
+/// player did. Attacks and Shot abilities both count as attacks; poison
+/// ticking at the start of a turn counts as damage from whoever poisoned
+/// the unit last.
+pub fn summarize_game(mut state: GameState, actions: &[(Seat, ActionPayload)]) -> Result<GameSummary, GameError> {
+    let mut seats = vec![SeatSummary::default(); state.teams.len()];
+    let mut finish = Finish::Unfinished;
+    let mut poisoned_by: Vec<(u32, Seat)> = Vec::new(); // Unit id and who poisoned it last
+    let hp = |state: &GameState, id: u32| state.units.iter().find(|u| u.id == id).map_or(0, |u| u.hp);
+
+    for (seat, payload) in actions {
+        let seat = *seat;
+        let hit = match payload {
+            ActionPayload::Attack(attack) => Some((attack.attacker_id, attack.target_id)),
+            ActionPayload::Ability(used) if used.ability.kind == AbilityKind::Shot => {
+                used.target_id.map(|target_id| (used.unit_id, target_id))
+            }
+            _ => None,
+        };
+        let attack = hit.and_then(|(attacker_id, target_id)| {
+            let attacker = state.units.iter().find(|u| u.id == attacker_id)?;
+            let target = state.units.iter().find(|u| u.id == target_id)?;
+            let from = state.terrain[attacker.y as usize][attacker.x as usize];
+            Some((attacker_id, target_id, from, attacker.hp, target.hp, target.owner))
+        });
+        let was_finished = state.status == GameStatus::Finished;
+        let turn_before = state.turn_number;
+        let units_before = state.units.clone();
+        state.apply(seat, payload)?;
+
+        if let ActionPayload::Ability(used) = payload {
+            if let (AbilityKind::Poison, Some(target_id)) = (used.ability.kind, used.target_id) {
+                poisoned_by.retain(|&(id, _)| id != target_id);
+                poisoned_by.push((target_id, seat));
+            }
+        }
+        if state.turn_number != turn_before {
+            for (unit_id, lost) in effect_damage(&units_before, &state) {
+                seats[state.current_turn].damage_taken += lost;
+                if let Some(&(_, by)) = poisoned_by.iter().find(|&&(id, _)| id == unit_id) {
+                    seats[by].damage_dealt += lost;
+                }
+            }
+        }
+
+        if let Some((attacker_id, target_id, from, attacker_hp, target_hp, defender)) = attack {
+            let attacker_hp_after = hp(&state, attacker_id);
+            let target_hp_after = hp(&state, target_id);
+            seats[seat].count_attack_from(from);
+            seats[seat].damage_dealt += (target_hp - target_hp_after) as u32;
+            seats[defender].damage_taken += (target_hp - target_hp_after) as u32;
+            seats[defender].damage_dealt += (attacker_hp - attacker_hp_after) as u32;
+            seats[seat].damage_taken += (attacker_hp - attacker_hp_after) as u32;
+            if target_hp_after == 0 {
+                seats[seat].units_killed += 1;
+                seats[defender].units_lost += 1;
+            }
+            if attacker_hp_after == 0 {
+                seats[defender].units_killed += 1;
+                seats[seat].units_lost += 1;
+            }
+        }
+
+        if !was_finished && state.status == GameStatus::Finished {
+            finish = match (payload, attack) {
+                (_, Some((_, _, from, _, _, defender))) => {
+                    // A counter-attack can finish the game for the defender's side
+                    let by = if state.winner == Some(state.teams[defender]) { defender } else { seat };
+                    Finish::Elimination { by, from }
+                }
+                (ActionPayload::Surrender | ActionPayload::Forfeit, _) => Finish::Surrender,
+                _ => match state.winner {
+                    Some(winner) if state.points[winner] >= VICTORY_POINT_TARGET => Finish::VictoryPoints,
+                    _ => Finish::TurnLimit,
+                },
+            };
+        }
+    }
+
+    Ok(GameSummary { seats, teams: state.teams.clone(), winner: state.winner, finish })
+}
+
+// ===== XP =====
+
+/// XP a player earns from a game
+pub fn xp_for_game(summary: &GameSummary, seat: Seat) -> u32 {
+    let win_bonus = if summary.won(seat) { XP_PER_WIN } else { 0 };
+    XP_PER_GAME + win_bonus + summary.seats[seat].damage_dealt / DAMAGE_PER_XP
+}
+
+/// Level reached with a total amount of XP. Players start at level 1; level 2
+/// takes `LEVEL_XP_STEP` XP and each level after takes `LEVEL_XP_STEP` more.
+pub fn level_for_xp(xp: u32) -> u32 {
+    let mut level = 1;
+    let mut remaining = xp;
+    let mut needed = LEVEL_XP_STEP;
+    while remaining >= needed {
+        remaining -= needed;
+        level += 1;
+        needed += LEVEL_XP_STEP;
+    }
+    level
+}
+
+// ===== ACHIEVEMENTS =====
+
+/// Achievements a player earned with this game. `games_played` includes it.
+/// Already-unlocked ones are filtered out by the caller.
+pub fn earned_achievements(summary: &GameSummary, seat: Seat, games_played: u32) -> Vec<AchievementKind> {
+    let won = summary.won(seat);
+    let mut earned = Vec::new();
+    if won {
+        earned.push(AchievementKind::FirstVictory);
+    }
+    // A surrender ends the game before anything has been risked
+    if won && summary.finish != Finish::Surrender && summary.seats[seat].damage_taken == 0 {
+        earned.push(AchievementKind::Flawless);
+    }
+    if won && summary.finish == (Finish::Elimination { by: seat, from: TerrainType::Plains }) {
+        earned.push(AchievementKind::OpenGround);
+    }
+    if won && summary.finish == Finish::VictoryPoints {
+        earned.push(AchievementKind::Conqueror);
+    }
+    if games_played >= VETERAN_GAMES {
+        earned.push(AchievementKind::Veteran);
+    }
+    earned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AbilityAction, AbilitySpec, AttackAction, Direction, JoinAction, Position, SpawnedUnit, UnitClass};
+
+    fn join(unit_id: u32, class: UnitClass, x: u8, y: u8, facing: Direction) -> ActionPayload {
+        let unit = SpawnedUnit { unit_id, class, position: Position { x, y }, facing };
+        ActionPayload::Join(JoinAction { units: vec![unit] })
+    }
+
+    fn open_board() -> GameState {
+        GameState::new(vec![vec![TerrainType::Plains; 5]; 5], vec![vec![ObjectiveType::None; 5]; 5])
+    }
+
+    #[test]
+    fn counts_damage_kills_and_finish() {
+        let actions = vec![
+            (0, join(1, UnitClass::Tank, 2, 1, Direction::South)),
+            (1, join(2, UnitClass::Infantry, 2, 2, Direction::North)),
+            (0, ActionPayload::Attack(AttackAction {
+                attacker_id: 1,
+                target_id: 2,
+                damage: 50,
+                flanking: false,
+                counter_damage: 11,
+                range: 1,
+                obstruction: None,
+            })),
+            (0, ActionPayload::EndTurn),
+            (1, ActionPayload::EndTurn),
+            (0, ActionPayload::Attack(AttackAction {
+                attacker_id: 1,
+                target_id: 2,
+                damage: 50,
+                flanking: false,
+                counter_damage: 0,
+                range: 1,
+                obstruction: None,
+            })),
+        ];
+        let summary = summarize_game(open_board(), &actions).unwrap();
+        assert_eq!(summary.winner, Some(0));
+        assert_eq!(summary.finish, Finish::Elimination { by: 0, from: TerrainType::Plains });
+        assert_eq!(summary.seats[0].damage_dealt, 100);
+        assert_eq!(summary.seats[0].damage_taken, 11);
+        assert_eq!(summary.seats[1].damage_dealt, 11);
+        assert_eq!(summary.seats[0].units_killed, 1);
+        assert_eq!(summary.seats[1].units_lost, 1);
+        assert_eq!(summary.seats[0].attacks_from, vec![(TerrainType::Plains, 2)]);
+
+        let earned = earned_achievements(&summary, 0, 1);
+        assert_eq!(earned, vec![AchievementKind::FirstVictory, AchievementKind::OpenGround]);
+        assert!(earned_achievements(&summary, 1, 1).is_empty());
+    }
+
+    #[test]
+    fn surrender_wins_are_never_flawless() {
+        let actions = vec![
+            (0, join(1, UnitClass::Scout, 0, 0, Direction::South)),
+            (1, join(2, UnitClass::Scout, 4, 4, Direction::North)),
+            (1, ActionPayload::Surrender),
+        ];
+        let summary = summarize_game(open_board(), &actions).unwrap();
+        assert_eq!(summary.finish, Finish::Surrender);
+        assert_eq!(earned_achievements(&summary, 0, 10), vec![
+            AchievementKind::FirstVictory,
+            AchievementKind::Veteran,
+        ]);
+    }
+
+    #[test]
+    fn poison_counts_as_damage() {
+        let dart = AbilitySpec { name: "poison_dart".to_string(), kind: AbilityKind::Poison, range: 2, power: 10, cooldown: 3 };
+        let actions = vec![
+            (0, join(1, UnitClass::Scout, 2, 0, Direction::South)),
+            (1, join(2, UnitClass::Infantry, 2, 2, Direction::North)),
+            (0, ActionPayload::Ability(AbilityAction { unit_id: 1, target_id: Some(2), ability: dart, amount: 10 })),
+            (0, ActionPayload::EndTurn),
+            (1, ActionPayload::EndTurn),
+            (0, ActionPayload::EndTurn),
+        ];
+        let summary = summarize_game(open_board(), &actions).unwrap();
+        assert_eq!(summary.seats[0].damage_dealt, 20);
+        assert_eq!(summary.seats[1].damage_taken, 20);
+        assert_eq!(summary.seats[0].damage_taken, 0);
+    }
+
+    #[test]
+    fn xp_and_levels() {
+        let summary = GameSummary {
+            seats: vec![SeatSummary { damage_dealt: 95, ..SeatSummary::default() }, SeatSummary::default()],
+            teams: vec![0, 1],
+            winner: Some(0),
+            finish: Finish::Surrender,
+        };
+        assert_eq!(xp_for_game(&summary, 0), 159);
+        assert_eq!(xp_for_game(&summary, 1), XP_PER_GAME);
+
+        assert_eq!(level_for_xp(0), 1);
+        assert_eq!(level_for_xp(99), 1);
+        assert_eq!(level_for_xp(100), 2);
+        assert_eq!(level_for_xp(299), 2);
+        assert_eq!(level_for_xp(300), 3);
+    }
+}
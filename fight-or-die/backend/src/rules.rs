@@ -0,0 +1,406 @@
+//! Game rules as plain functions: class stats, damage, distances,
+//! pathfinding and line of sight. Terrain properties live in `terrain.rs`. Nothing here reads or writes a
+//! table, so it all runs (and is tested) without a SpacetimeDB host.
+
+use crate::terrain::terrain_properties;
+use crate::{Direction, TerrainType, UnitClass};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// ===== CONSTANTS =====
+
+/// Every hit deals at least this much, whatever the terrain and matchup
+pub const MIN_DAMAGE: i32 = 5;
+
+/// Damage removed per point of defense (terrain or Fortify)
+pub const DAMAGE_PER_DEFENSE: i32 = 10;
+
+/// Defenders that survive strike back with this percent of their damage
+pub const COUNTER_PERCENT: i32 = 50;
+
+/// Extra damage for hitting a unit from the side/rear or next to an ally
+pub const FLANKING_BONUS: i32 = 10;
+
+/// Percent of its damage an attack loses for every tile past the first
+pub const RANGE_FALLOFF_PERCENT: i32 = 10;
+
+/// Defense a target gets when a shot passes over cover (Forest) on the way
+pub const COVER_DEFENSE: i32 = 1;
+
+/// Victory points a player earns per capture point held at the end of their turn
+pub const VP_PER_CAPTURE_POINT: u32 = 1;
+
+/// Victory points needed to win outright
+pub const VICTORY_POINT_TARGET: u32 = 10;
+
+/// After this many rounds (every seat still playing has one turn a round) the
+/// game goes to the team with more victory points, or ends in a draw
+pub const ROUND_LIMIT: u32 = 20;
+
+/// HP restored at the start of its owner's turn to a unit on a healing station
+pub const HEALING_STATION_HP: i32 = 20;
+
+// ===== BALANCE =====
+
+/// Per-class stats. Not a table - the reducers only ever read these values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClassStats {
+    pub max_hp: i32,
+    pub movement: u8, // Half steps, like terrain move costs
+    pub damage: i32,
+    pub attack_range: u8, // Manhattan distance
+    pub vision: u8,       // Manhattan distance, blocked by Forest/Mountain; see `terrain::vision_from`
+    pub cost: u32,        // Deployment points
+}
+
+/// Base stats for each unit class. Rebalance here, not in the reducers.
+pub fn get_class_stats(class: UnitClass) -> ClassStats {
+    match class {
+        UnitClass::Infantry => ClassStats { max_hp: 100, movement: 6, damage: 30, attack_range: 1, vision: 3, cost: 3 },
+        UnitClass::Tank => ClassStats { max_hp: 120, movement: 4, damage: 40, attack_range: 1, vision: 2, cost: 4 },
+        UnitClass::Scout => ClassStats { max_hp: 80, movement: 8, damage: 20, attack_range: 1, vision: 5, cost: 2 },
+        UnitClass::Archer => ClassStats { max_hp: 70, movement: 6, damage: 25, attack_range: 3, vision: 4, cost: 3 },
+        UnitClass::Artillery => ClassStats { max_hp: 60, movement: 4, damage: 40, attack_range: 4, vision: 2, cost: 5 },
+    }
+}
+
+/// Damage multiplier in percent when `attacker` hits `defender`.
+/// Rock-paper-scissors: Tank beats Infantry, Infantry beats Scout, Scout beats Tank.
+pub fn get_class_multiplier(attacker: UnitClass, defender: UnitClass) -> i32 {
+    match (attacker, defender) {
+        (UnitClass::Tank, UnitClass::Infantry) => 125,
+        (UnitClass::Infantry, UnitClass::Scout) => 125,
+        (UnitClass::Scout, UnitClass::Tank) => 125,
+        (UnitClass::Infantry, UnitClass::Tank) => 75,
+        (UnitClass::Scout, UnitClass::Infantry) => 75,
+        (UnitClass::Tank, UnitClass::Scout) => 75,
+        _ => 100,
+    }
+}
+
+// ===== RULES =====
+
+/// Convert terrain type to defense value (damage reduction)
+pub fn get_terrain_defense(terrain: TerrainType) -> i32 {
+    terrain_properties(terrain).defense
+}
+
+/// Whether a tile stops line of sight through it
+pub fn blocks_sight(terrain: TerrainType) -> bool {
+    terrain_properties(terrain).blocks_sight
+}
+
+/// Movement points needed to enter a tile, or `None` if it can't be entered
+pub fn get_terrain_move_cost(terrain: TerrainType) -> Option<u8> {
+    let properties = terrain_properties(terrain);
+    properties.passable.then_some(properties.move_cost)
+}
+
+/// Damage dealt by one attack: class damage scaled by the matchup, plus
+/// any situational bonus, minus the defender's terrain bonus
+pub fn calculate_damage(attacker: UnitClass, defender: UnitClass, defender_terrain: TerrainType, bonus: i32) -> i32 {
+    let base_damage = get_class_stats(attacker).damage * get_class_multiplier(attacker, defender) / 100;
+    let defense = get_terrain_defense(defender_terrain);
+    (base_damage + bonus - defense * DAMAGE_PER_DEFENSE).max(MIN_DAMAGE)
+}
+
+/// Damage left of a hit after falloff over `distance` tiles
+pub fn apply_falloff(damage: i32, distance: u8) -> i32 {
+    let falloff = RANGE_FALLOFF_PERCENT * (distance as i32 - 1).max(0);
+    (damage * (100 - falloff) / 100).max(MIN_DAMAGE)
+}
+
+/// What stands between an attacker and its target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineOfFire {
+    Clear,
+    Cover((u8, u8)),   // First tile on the way that gives the target cover
+    Blocked((u8, u8)), // First tile on the way that stops the shot
+}
+
+/// Walk the Bresenham line between two tiles, ends excluded. A tile that
+/// blocks shots (Mountain) or holds one of `units` stops the shot; the first
+/// one that only blocks sight (Forest) gives the target cover.
+pub fn line_of_fire(terrain: &[Vec<TerrainType>], units: &[(u8, u8)], from: (u8, u8), to: (u8, u8)) -> LineOfFire {
+    let line = line_between(from, to);
+    let mut result = LineOfFire::Clear;
+    for &(x, y) in line.iter().skip(1).take(line.len().saturating_sub(2)) {
+        let properties = terrain_properties(terrain[y as usize][x as usize]);
+        if properties.blocks_shots || units.contains(&(x, y)) {
+            return LineOfFire::Blocked((x, y));
+        }
+        if properties.blocks_sight && result == LineOfFire::Clear {
+            result = LineOfFire::Cover((x, y));
+        }
+    }
+    result
+}
+
+/// Check if two positions are adjacent (no diagonal)
+pub fn is_adjacent(x1: u8, y1: u8, x2: u8, y2: u8) -> bool {
+    let dx = (x1 as i32 - x2 as i32).abs();
+    let dy = (y1 as i32 - y2 as i32).abs();
+    (dx == 1 && dy == 0) || (dx == 0 && dy == 1)
+}
+
+/// Calculate Manhattan distance for movement
+pub fn calculate_distance(x1: u8, y1: u8, x2: u8, y2: u8) -> u8 {
+    ((x1 as i32 - x2 as i32).abs() + (y1 as i32 - y2 as i32).abs()) as u8
+}
+
+/// Direction to face when looking from one tile towards another.
+/// The longer axis wins; ties face North/South.
+pub fn direction_towards(from: (u8, u8), to: (u8, u8)) -> Direction {
+    let dx = to.0 as i32 - from.0 as i32;
+    let dy = to.1 as i32 - from.1 as i32;
+    if dx.abs() > dy.abs() {
+        if dx > 0 { Direction::East } else { Direction::West }
+    } else if dy > 0 {
+        Direction::South
+    } else {
+        Direction::North
+    }
+}
+
+/// Cheapest route from `from` to `to` (Dijkstra over terrain move costs).
+/// Impassable and `blocked` tiles can't be entered or passed through, and
+/// routes costing more than `max_cost` are not explored. Returns the tiles
+/// stepped on, excluding the start, and the total movement cost.
+pub fn find_path(
+    terrain: &[Vec<TerrainType>],
+    blocked: &[(u8, u8)],
+    from: (u8, u8),
+    to: (u8, u8),
+    max_cost: u8,
+) -> Option<(Vec<(u8, u8)>, u8)> {
+    let height = terrain.len();
+    let width = terrain.first().map_or(0, |row| row.len());
+    let mut best = vec![vec![u8::MAX; width]; height];
+    let mut came_from: Vec<Vec<Option<(u8, u8)>>> = vec![vec![None; width]; height];
+    let mut queue = BinaryHeap::new();
+    best[from.1 as usize][from.0 as usize] = 0;
+    queue.push(Reverse((0u8, from.0, from.1)));
+    
+    while let Some(Reverse((cost, x, y))) = queue.pop() {
+        if (x, y) == to {
+            // Walk back to the start to recover the route
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(prev) = came_from[current.1 as usize][current.0 as usize] {
+                if prev == from {
+                    break;
+                }
+                path.push(prev);
+                current = prev;
+            }
+            path.reverse();
+            return Some((path, cost));
+        }
+        if cost > best[y as usize][x as usize] {
+            continue;
+        }
+        
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbours {
+            if nx as usize >= width || ny as usize >= height || blocked.contains(&(nx, ny)) {
+                continue;
+            }
+            let step_cost = match get_terrain_move_cost(terrain[ny as usize][nx as usize]) {
+                Some(step_cost) => step_cost,
+                None => continue,
+            };
+            let next_cost = cost.saturating_add(step_cost);
+            if next_cost <= max_cost && next_cost < best[ny as usize][nx as usize] {
+                best[ny as usize][nx as usize] = next_cost;
+                came_from[ny as usize][nx as usize] = Some((x, y));
+                queue.push(Reverse((next_cost, nx, ny)));
+            }
+        }
+    }
+    None
+}
+
+/// Tiles on the Bresenham line between two tiles, both ends included
+pub fn line_between(from: (u8, u8), to: (u8, u8)) -> Vec<(u8, u8)> {
+    let (mut x, mut y) = (from.0 as i32, from.1 as i32);
+    let (x1, y1) = (to.0 as i32, to.1 as i32);
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let sx = if x < x1 { 1 } else { -1 };
+    let sy = if y < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut line = Vec::new();
+    
+    loop {
+        line.push((x as u8, y as u8));
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+    line
+}
+
+/// A tile is seen if no tile strictly between it and the viewer blocks sight
+pub fn has_line_of_sight(terrain: &[Vec<TerrainType>], from: (u8, u8), to: (u8, u8)) -> bool {
+    let line = line_between(from, to);
+    if line.len() <= 2 {
+        return true;
+    }
+    line[1..line.len() - 1]
+        .iter()
+        .all(|&(x, y)| !blocks_sight(terrain[y as usize][x as usize]))
+}
+
+/// Every tile seen by at least one of `viewers`, given as (x, y, vision radius)
+pub fn compute_visible_tiles(terrain: &[Vec<TerrainType>], viewers: &[(u8, u8, u8)]) -> Vec<(u8, u8)> {
+    let height = terrain.len() as u8;
+    let width = terrain.first().map_or(0, |row| row.len()) as u8;
+    let mut visible = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let seen = viewers.iter().any(|&(vx, vy, vision)| {
+                calculate_distance(vx, vy, x, y) <= vision && has_line_of_sight(terrain, (vx, vy), (x, y))
+            });
+            if seen {
+                visible.push((x, y));
+            }
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plains(width: usize, height: usize) -> Vec<Vec<TerrainType>> {
+        vec![vec![TerrainType::Plains; width]; height]
+    }
+
+    #[test]
+    fn damage_follows_class_matchups() {
+        assert_eq!(calculate_damage(UnitClass::Tank, UnitClass::Infantry, TerrainType::Plains, 0), 50);
+        assert_eq!(calculate_damage(UnitClass::Infantry, UnitClass::Tank, TerrainType::Plains, 0), 22);
+        assert_eq!(calculate_damage(UnitClass::Infantry, UnitClass::Infantry, TerrainType::Plains, 0), 30);
+    }
+
+    #[test]
+    fn terrain_and_bonus_adjust_damage() {
+        assert_eq!(calculate_damage(UnitClass::Infantry, UnitClass::Infantry, TerrainType::Forest, 0), 20);
+        assert_eq!(calculate_damage(UnitClass::Infantry, UnitClass::Infantry, TerrainType::Mountain, 0), 10);
+        assert_eq!(calculate_damage(UnitClass::Infantry, UnitClass::Infantry, TerrainType::Plains, FLANKING_BONUS), 40);
+    }
+
+    #[test]
+    fn damage_never_drops_below_minimum() {
+        assert_eq!(calculate_damage(UnitClass::Scout, UnitClass::Infantry, TerrainType::Mountain, 0), MIN_DAMAGE);
+    }
+
+    #[test]
+    fn distance_and_adjacency() {
+        assert_eq!(calculate_distance(0, 0, 3, 4), 7);
+        assert!(is_adjacent(2, 2, 2, 3));
+        assert!(!is_adjacent(2, 2, 3, 3));
+        assert!(!is_adjacent(2, 2, 2, 2));
+    }
+
+    #[test]
+    fn facing_uses_longer_axis() {
+        assert_eq!(direction_towards((2, 2), (4, 3)), Direction::East);
+        assert_eq!(direction_towards((2, 2), (0, 2)), Direction::West);
+        assert_eq!(direction_towards((2, 2), (3, 3)), Direction::South);
+        assert_eq!(direction_towards((2, 2), (2, 0)), Direction::North);
+    }
+
+    #[test]
+    fn path_prefers_cheap_terrain() {
+        let mut terrain = plains(4, 2);
+        terrain[0][1] = TerrainType::Mountain;
+        terrain[0][2] = TerrainType::Mountain;
+        let (path, cost) = find_path(&terrain, &[], (0, 0), (3, 0), 20).unwrap();
+        assert_eq!(cost, 10);
+        assert_eq!(path, vec![(0, 1), (1, 1), (2, 1), (3, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn path_respects_blockers_and_budget() {
+        let terrain = plains(3, 1);
+        assert!(find_path(&terrain, &[(1, 0)], (0, 0), (2, 0), 10).is_none());
+        assert!(find_path(&terrain, &[], (0, 0), (2, 0), 3).is_none());
+        assert_eq!(find_path(&terrain, &[], (0, 0), (2, 0), 4).unwrap().1, 4);
+    }
+
+    #[test]
+    fn roads_are_cheaper_than_plains() {
+        let mut terrain = plains(6, 2);
+        assert_eq!(find_path(&terrain, &[], (0, 0), (5, 0), 20).unwrap().1, 10);
+        for tile in terrain[1].iter_mut() {
+            *tile = TerrainType::Road;
+        }
+        // Stepping down to the road and back up still beats the plains
+        let (path, cost) = find_path(&terrain, &[], (0, 0), (5, 0), 20).unwrap();
+        assert_eq!(cost, 8);
+        assert_eq!(path, vec![(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (5, 0)]);
+    }
+
+    #[test]
+    fn water_is_impassable() {
+        let mut terrain = plains(3, 2);
+        terrain[0][1] = TerrainType::Water;
+        let (path, cost) = find_path(&terrain, &[], (0, 0), (2, 0), 10).unwrap();
+        assert_eq!((path.len(), cost), (4, 8));
+        assert!(find_path(&terrain, &[], (0, 0), (1, 0), 10).is_none());
+    }
+
+    #[test]
+    fn shots_are_stopped_by_mountains_and_units() {
+        let mut terrain = plains(5, 1);
+        assert_eq!(line_of_fire(&terrain, &[], (0, 0), (4, 0)), LineOfFire::Clear);
+        terrain[0][1] = TerrainType::Forest;
+        terrain[0][3] = TerrainType::Forest;
+        assert_eq!(line_of_fire(&terrain, &[], (0, 0), (4, 0)), LineOfFire::Cover((1, 0)));
+        assert_eq!(line_of_fire(&terrain, &[(2, 0)], (0, 0), (4, 0)), LineOfFire::Blocked((2, 0)));
+        terrain[0][2] = TerrainType::Mountain;
+        assert_eq!(line_of_fire(&terrain, &[], (0, 0), (4, 0)), LineOfFire::Blocked((2, 0)));
+        // Cover and blockers only count between the two units
+        assert_eq!(line_of_fire(&terrain, &[], (1, 0), (2, 0)), LineOfFire::Clear);
+
+        assert_eq!(apply_falloff(40, 1), 40);
+        assert_eq!(apply_falloff(40, 4), 28);
+        assert_eq!(apply_falloff(6, 4), MIN_DAMAGE);
+    }
+
+    #[test]
+    fn forest_blocks_line_of_sight() {
+        let mut terrain = plains(5, 1);
+        assert!(has_line_of_sight(&terrain, (0, 0), (4, 0)));
+        terrain[0][2] = TerrainType::Forest;
+        assert!(!has_line_of_sight(&terrain, (0, 0), (4, 0)));
+        // The blocking tile itself is still visible
+        assert!(has_line_of_sight(&terrain, (0, 0), (2, 0)));
+    }
+
+    #[test]
+    fn visibility_is_limited_by_range() {
+        let terrain = plains(5, 5);
+        let visible = compute_visible_tiles(&terrain, &[(0, 0, 2)]);
+        assert!(visible.contains(&(1, 1)));
+        assert!(visible.contains(&(0, 2)));
+        assert!(!visible.contains(&(2, 1)));
+        assert_eq!(visible.len(), 6);
+    }
+}
@@ -0,0 +1,65 @@
+//! Sealing rows meant for one player. SpacetimeDB 0.10 lets any client
+//! subscribe to every row of a public table, so per-player data such as a
+//! fog-of-war view is encrypted with a key only that player and the module
+//! know before it goes into one.
+
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// Length of a view key in bytes
+pub const VIEW_KEY_LENGTH: usize = 32;
+
+/// Length views are padded to before sealing, so the size of a sealed view
+/// doesn't give away how many tiles and units its player can see. A view of
+/// the largest board with every squad in sight fits in one.
+pub const SEALED_VIEW_LENGTH: usize = 8192;
+
+/// Encrypt or decrypt `data` with ChaCha20 (the original 64-bit nonce
+/// variant). Sealing the result again with the same key and nonce gives the
+/// data back, so a nonce must never be used twice with one key.
+pub fn seal(key: &[u8; VIEW_KEY_LENGTH], nonce: u64, data: &[u8]) -> Vec<u8> {
+    let mut cipher = ChaCha20Rng::from_seed(*key);
+    cipher.set_stream(nonce);
+    let mut keystream = vec![0; data.len()];
+    cipher.fill_bytes(&mut keystream);
+    data.iter().zip(keystream).map(|(byte, k)| byte ^ k).collect()
+}
+
+/// Zero-pad encoded `data` to a multiple of SEALED_VIEW_LENGTH bytes. A
+/// decoder reading the view from the front stops before the padding.
+pub fn pad(mut data: Vec<u8>) -> Vec<u8> {
+    let blocks = data.len().div_ceil(SEALED_VIEW_LENGTH).max(1);
+    data.resize(blocks * SEALED_VIEW_LENGTH, 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_chacha20_keystream() {
+        // First block of the all-zero key and nonce test vector
+        let keystream = seal(&[0; VIEW_KEY_LENGTH], 0, &[0; 8]);
+        assert_eq!(keystream, [0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90]);
+    }
+
+    #[test]
+    fn sealing_twice_opens() {
+        let key = [7; VIEW_KEY_LENGTH];
+        let view = b"unit 3 at 4,2".to_vec();
+        let sealed = seal(&key, 5, &view);
+        assert_ne!(sealed, view);
+        assert_ne!(seal(&key, 6, &view), sealed);
+        assert_eq!(seal(&key, 5, &sealed), view);
+    }
+
+    #[test]
+    fn views_are_padded_to_the_same_length() {
+        assert_eq!(pad(Vec::new()).len(), SEALED_VIEW_LENGTH);
+        let padded = pad(vec![1, 2, 3]);
+        assert_eq!(padded.len(), SEALED_VIEW_LENGTH);
+        assert_eq!(padded[..4], [1, 2, 3, 0]);
+        assert_eq!(pad(vec![1; SEALED_VIEW_LENGTH + 1]).len(), 2 * SEALED_VIEW_LENGTH);
+    }
+}
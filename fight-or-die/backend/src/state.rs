@@ -0,0 +1,1105 @@
+//! In-memory game state and the turn-level rules that act on it.
+//!
+//! `GameState` never touches a table. The reducers load one from the tables,
+//! call a method here and write the result back; replays start from a board
+//! and `apply` logged `ActionPayload`s one at a time, checking every logged
+//! outcome (paths, damage, counter-attacks) against what the rules produce.
+
+use crate::abilities::{needs_target, uses_attack};
+use crate::effects::{add_effect, has_effect, start_turn, terrain_effect, total, POISON_TURNS};
+use crate::rules::{
+    apply_falloff, calculate_damage, calculate_distance, compute_visible_tiles, direction_towards, find_path,
+    get_class_stats, is_adjacent, line_of_fire, LineOfFire, COUNTER_PERCENT, COVER_DEFENSE, DAMAGE_PER_DEFENSE,
+    FLANKING_BONUS, HEALING_STATION_HP, MIN_DAMAGE, ROUND_LIMIT, VICTORY_POINT_TARGET, VP_PER_CAPTURE_POINT,
+};
+use crate::error::GameError;
+use crate::terrain::{elevation_bonus, terrain_properties, vision_from};
+use crate::{
+    AbilityAction, AbilityCooldown, AbilityKind, AbilitySpec, ActionPayload, ActiveEffect, AttackAction, Direction,
+    EffectKind, EffectSource, GameMode, GameStatus, MoveAction, ObjectiveType, Position, SpawnedUnit, TerrainType,
+    UnitClass,
+};
+
+/// A player's place in a game and in the turn order: 0 for player 1, 1 for
+/// player 2 and so on
+pub type Seat = usize;
+
+/// A side that wins or loses together. In duels and free-for-alls every
+/// seat is its own team, numbered like the seat.
+pub type Team = usize;
+
+/// Team of each seat, in seat order. Team games alternate sides: seats 0
+/// and 2 against seats 1 and 3.
+pub fn seat_teams(mode: &GameMode) -> Vec<Team> {
+    match mode {
+        GameMode::Duel => vec![0, 1],
+        GameMode::FreeForAll(players) => (0..*players as usize).collect(),
+        GameMode::Teams => vec![0, 1, 0, 1],
+    }
+}
+
+/// Number of teams in a game mode
+pub fn team_count(mode: &GameMode) -> usize {
+    match mode {
+        GameMode::Duel | GameMode::Teams => 2,
+        GameMode::FreeForAll(players) => *players as usize,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitState {
+    pub id: u32,
+    pub owner: Seat,
+    pub class: UnitClass,
+    pub x: u8,
+    pub y: u8,
+    pub hp: i32,
+    pub movement_left: u8,
+    pub has_attacked: bool,
+    pub facing: Direction,
+    pub effects: Vec<ActiveEffect>,      // Status effects, ticked at the start of the owner's turns
+    pub cooldowns: Vec<AbilityCooldown>, // Abilities not ready yet
+}
+
+impl UnitState {
+    /// Extra defense from effects such as Fortified
+    fn defense_bonus(&self) -> i32 {
+        total(&self.effects, EffectKind::Fortified)
+    }
+
+    fn is_stunned(&self) -> bool {
+        has_effect(&self.effects, EffectKind::Stun)
+    }
+
+    /// Put an ability's effect on this unit with the ability's power
+    fn add_ability_effect(&mut self, kind: EffectKind, ability: &AbilitySpec, turns_left: u8) {
+        let source = EffectSource::Ability(ability.name.clone());
+        add_effect(&mut self.effects, ActiveEffect { kind, magnitude: ability.power, turns_left, source });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureState {
+    pub x: u8,
+    pub y: u8,
+    pub owner: Option<Seat>,
+}
+
+/// Expected result of an attack and the defender's counter-attack
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CombatOutcome {
+    pub damage: i32,
+    pub flanking: bool,
+    pub range: u8,                        // Distance between the two units
+    pub obstruction: Option<(u8, u8)>,    // Cover the shot passed over, if any
+    pub counter_damage: i32, // 0 if the target dies or can't reach the attacker
+    pub target_hp_after: i32,
+    pub attacker_hp_after: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameState {
+    pub terrain: Vec<Vec<TerrainType>>,
+    pub objectives: Vec<Vec<ObjectiveType>>,
+    pub units: Vec<UnitState>,
+    pub capture_points: Vec<CaptureState>,
+    pub teams: Vec<Team>, // Team of each seat; its length is the number of seats
+    pub players_joined: usize,
+    pub current_turn: Seat,
+    pub turn_number: u32,
+    pub round: u32, // Starts at 1, +1 each time the turn order starts over
+    pub points: Vec<u32>, // Victory points per team
+    pub status: GameStatus,
+    pub winner: Option<Team>,
+}
+
+impl GameState {
+    /// A two-player game on this board before anyone has joined
+    pub fn new(terrain: Vec<Vec<TerrainType>>, objectives: Vec<Vec<ObjectiveType>>) -> Self {
+        let mut capture_points = Vec::new();
+        for (y, row) in objectives.iter().enumerate() {
+            for (x, objective) in row.iter().enumerate() {
+                if *objective == ObjectiveType::CapturePoint {
+                    capture_points.push(CaptureState { x: x as u8, y: y as u8, owner: None });
+                }
+            }
+        }
+
+        GameState {
+            terrain,
+            objectives,
+            units: Vec::new(),
+            capture_points,
+            teams: vec![0, 1],
+            players_joined: 0,
+            current_turn: 0,
+            turn_number: 1,
+            round: 1,
+            points: vec![0, 0],
+            status: GameStatus::WaitingForPlayers,
+            winner: None,
+        }
+    }
+
+    /// The same game with other seats and teams, e.g. from `seat_teams`
+    pub fn with_teams(mut self, teams: Vec<Team>) -> Self {
+        self.points = vec![0; teams.iter().max().map_or(0, |&t| t + 1)];
+        self.teams = teams;
+        self
+    }
+
+    /// Apply one logged action taken by `seat`. Fails if the action breaks
+    /// the rules or its logged outcome differs from what the rules give.
+    pub fn apply(&mut self, seat: Seat, payload: &ActionPayload) -> Result<(), GameError> {
+        match payload {
+            ActionPayload::Join(join) => self.join(seat, &join.units),
+            ActionPayload::Move(logged) => {
+                let result = self.move_unit(seat, logged.unit_id, logged.destination)?;
+                if result != *logged {
+                    return Err(GameError::Diverged(format!("Move diverged: logged {:?}, rules give {:?}", logged, result)));
+                }
+                Ok(())
+            }
+            ActionPayload::Attack(logged) => {
+                let result = self.attack(seat, logged.attacker_id, logged.target_id)?;
+                if result != *logged {
+                    return Err(GameError::Diverged(format!("Attack diverged: logged {:?}, rules give {:?}", logged, result)));
+                }
+                Ok(())
+            }
+            ActionPayload::Ability(logged) => {
+                let result = self.use_ability(seat, logged.unit_id, &logged.ability, logged.target_id)?;
+                if result != *logged {
+                    return Err(GameError::Diverged(format!("Ability diverged: logged {:?}, rules give {:?}", logged, result)));
+                }
+                Ok(())
+            }
+            ActionPayload::EndTurn | ActionPayload::Timeout => self.end_turn(seat),
+            ActionPayload::Surrender | ActionPayload::Forfeit => self.surrender(seat),
+        }
+    }
+
+    /// Add a player's starting roster. Seats join in order; the game starts
+    /// once the second player is in.
+    pub fn join(&mut self, seat: Seat, units: &[SpawnedUnit]) -> Result<(), GameError> {
+        if self.status != GameStatus::WaitingForPlayers {
+            return Err(GameError::GameNotJoinable);
+        }
+        if seat != self.players_joined {
+            return Err(GameError::SeatOutOfOrder(seat));
+        }
+
+        for spawned in units {
+            let stats = get_class_stats(spawned.class);
+            self.units.push(UnitState {
+                id: spawned.unit_id,
+                owner: seat,
+                class: spawned.class,
+                x: spawned.position.x,
+                y: spawned.position.y,
+                hp: stats.max_hp,
+                movement_left: stats.movement,
+                has_attacked: false,
+                facing: spawned.facing,
+                effects: Vec::new(),
+                cooldowns: Vec::new(),
+            });
+        }
+
+        self.players_joined += 1;
+        if self.players_joined == self.teams.len() {
+            self.status = GameStatus::InProgress;
+        }
+        Ok(())
+    }
+
+    /// Move a unit along its cheapest path. Units the player can't see don't
+    /// block the path or the destination; the move stops in front of the
+    /// first one it runs into, and the bump spends the unit's remaining
+    /// movement.
+    pub fn move_unit(&mut self, seat: Seat, unit_id: u32, to: Position) -> Result<MoveAction, GameError> {
+        self.check_turn(seat)?;
+        let idx = self.unit_index(unit_id)?;
+        let unit = &self.units[idx];
+        if unit.owner != seat {
+            return Err(GameError::NotOwner(unit_id));
+        }
+        if unit.hp <= 0 {
+            return Err(GameError::UnitDead(unit_id));
+        }
+        if unit.is_stunned() {
+            return Err(GameError::Stunned(unit_id));
+        }
+        if to.y as usize >= self.terrain.len() || to.x as usize >= self.terrain[0].len() {
+            return Err(GameError::OutOfBounds { x: to.x, y: to.y });
+        }
+        let from = (unit.x, unit.y);
+        if from == (to.x, to.y) {
+            return Err(GameError::AlreadyThere { x: to.x, y: to.y });
+        }
+
+        let blocked = self.known_unit_tiles(seat, unit_id);
+        if blocked.contains(&(to.x, to.y)) {
+            return Err(GameError::Occupied { x: to.x, y: to.y });
+        }
+        if !terrain_properties(self.terrain[to.y as usize][to.x as usize]).passable {
+            return Err(GameError::Impassable { x: to.x, y: to.y });
+        }
+        let (mut path, mut cost) = find_path(&self.terrain, &blocked, from, (to.x, to.y), unit.movement_left)
+            .ok_or(GameError::NoPath { x: to.x, y: to.y, movement: unit.movement_left })?;
+
+        let occupied: Vec<(u8, u8)> =
+            self.units.iter().filter(|u| u.id != unit_id && u.hp > 0).map(|u| (u.x, u.y)).collect();
+        let bumped = path.iter().position(|tile| occupied.contains(tile)).map(|step| {
+            let tile = path[step];
+            path.truncate(step);
+            cost = unit.movement_left;
+            tile
+        });
+        let end = path.last().copied().unwrap_or(from);
+        let facing = match bumped {
+            Some(tile) => direction_towards(end, tile),
+            None => direction_towards(if path.len() > 1 { path[path.len() - 2] } else { from }, end),
+        };
+        let unit = &mut self.units[idx];
+        unit.facing = facing;
+        unit.x = end.0;
+        unit.y = end.1;
+        unit.movement_left -= cost;
+
+        Ok(MoveAction {
+            unit_id,
+            from: Position { x: from.0, y: from.1 },
+            to: Position { x: end.0, y: end.1 },
+            path: path.into_iter().map(|(x, y)| Position { x, y }).collect(),
+            cost,
+            destination: to,
+            bumped: bumped.map(|(x, y)| Position { x, y }),
+        })
+    }
+
+    /// Work out an attack without changing anything. Works outside your own
+    /// turn so players can plan ahead.
+    pub fn preview_attack(&self, seat: Seat, attacker_id: u32, target_id: u32) -> Result<CombatOutcome, GameError> {
+        if self.status != GameStatus::InProgress {
+            return Err(GameError::GameNotInProgress);
+        }
+        let attacker = &self.units[self.unit_index(attacker_id)?];
+        let target = &self.units[self.visible_unit_index(seat, target_id)?];
+        if attacker.owner != seat {
+            return Err(GameError::NotOwner(attacker_id));
+        }
+        if attacker.hp <= 0 {
+            return Err(GameError::UnitDead(attacker_id));
+        }
+        if attacker.is_stunned() {
+            return Err(GameError::Stunned(attacker_id));
+        }
+        if target.hp <= 0 {
+            return Err(GameError::TargetDead(target_id));
+        }
+        if self.teams[target.owner] == self.teams[attacker.owner] {
+            return Err(GameError::FriendlyFire(target_id));
+        }
+        let distance = calculate_distance(attacker.x, attacker.y, target.x, target.y);
+        let range = get_class_stats(attacker.class).attack_range;
+        if distance > range {
+            return Err(GameError::OutOfRange { range });
+        }
+        let obstruction = match self.line_of_fire_between(attacker, target) {
+            LineOfFire::Blocked((x, y)) => return Err(GameError::LineOfFireBlocked { x, y }),
+            LineOfFire::Cover(tile) => Some(tile),
+            LineOfFire::Clear => None,
+        };
+
+        // Flanking: hit from outside the target's front, or with an ally (a
+        // teammate's unit counts) next to it
+        let from_front = direction_towards((target.x, target.y), (attacker.x, attacker.y)) == target.facing;
+        let ally_adjacent = self.units.iter().any(|u| {
+            self.teams[u.owner] == self.teams[attacker.owner]
+                && u.id != attacker.id
+                && u.hp > 0
+                && is_adjacent(u.x, u.y, target.x, target.y)
+        });
+        let flanking = !from_front || ally_adjacent;
+        let bonus = if flanking { FLANKING_BONUS } else { 0 };
+        let target_terrain = self.terrain[target.y as usize][target.x as usize];
+        let attacker_terrain = self.terrain[attacker.y as usize][attacker.x as usize];
+        let cover = if obstruction.is_some() { COVER_DEFENSE } else { 0 };
+        let defense = (target.defense_bonus() + cover) * DAMAGE_PER_DEFENSE;
+        let bonus = bonus + elevation_bonus(attacker_terrain, target_terrain) - defense;
+        let damage = apply_falloff(calculate_damage(attacker.class, target.class, target_terrain, bonus), distance);
+        let target_hp_after = (target.hp - damage).max(0);
+
+        // Survivors strike back if the attacker is within their own range
+        // and line of fire, unless they're stunned
+        let counter_shot = self.line_of_fire_between(target, attacker);
+        let can_counter = !target.is_stunned()
+            && distance <= get_class_stats(target.class).attack_range
+            && !matches!(counter_shot, LineOfFire::Blocked(_));
+        let counter_damage = if target_hp_after > 0 && can_counter {
+            let cover = if counter_shot == LineOfFire::Clear { 0 } else { COVER_DEFENSE };
+            let defense = (attacker.defense_bonus() + cover) * DAMAGE_PER_DEFENSE;
+            let bonus = elevation_bonus(target_terrain, attacker_terrain) - defense;
+            let counter = calculate_damage(target.class, attacker.class, attacker_terrain, bonus);
+            apply_falloff(counter, distance) * COUNTER_PERCENT / 100
+        } else {
+            0
+        };
+
+        Ok(CombatOutcome {
+            damage,
+            flanking,
+            range: distance,
+            obstruction,
+            counter_damage,
+            target_hp_after,
+            attacker_hp_after: (attacker.hp - counter_damage).max(0),
+        })
+    }
+
+    /// Attack with flanking and counter-attack, from as far as the attacker's
+    /// range and line of fire allow. Wiping out a squad eliminates
+    /// its player, and the game ends when only one team has units left.
+    pub fn attack(&mut self, seat: Seat, attacker_id: u32, target_id: u32) -> Result<AttackAction, GameError> {
+        self.check_turn(seat)?;
+        let a = self.unit_index(attacker_id)?;
+        let t = self.unit_index(target_id)?;
+        if self.units[a].owner == seat && self.units[a].has_attacked {
+            return Err(GameError::AlreadyAttacked(attacker_id));
+        }
+        let outcome = self.preview_attack(seat, attacker_id, target_id)?;
+
+        let target_pos = (self.units[t].x, self.units[t].y);
+        self.units[t].hp = outcome.target_hp_after;
+        let attacker = &mut self.units[a];
+        attacker.facing = direction_towards((attacker.x, attacker.y), target_pos);
+        attacker.hp = outcome.attacker_hp_after;
+        attacker.has_attacked = true;
+        self.check_last_team_standing();
+
+        Ok(AttackAction {
+            attacker_id,
+            target_id,
+            damage: outcome.damage,
+            flanking: outcome.flanking,
+            counter_damage: outcome.counter_damage,
+            range: outcome.range,
+            obstruction: outcome.obstruction.map(|(x, y)| Position { x, y }),
+        })
+    }
+
+    /// Use an ability. Shot, Heal, Poison and Stun are aimed at another unit
+    /// in line of fire and take the unit's attack for the turn; a Shot loses
+    /// damage to cover and falloff like an attack. Fortify and Dash are used on
+    /// the unit itself (`target_id` None) and don't. The ability is then
+    /// unavailable for `cooldown` of the owner's turns.
+    pub fn use_ability(
+        &mut self,
+        seat: Seat,
+        unit_id: u32,
+        ability: &AbilitySpec,
+        target_id: Option<u32>,
+    ) -> Result<AbilityAction, GameError> {
+        self.check_turn(seat)?;
+        let u = self.unit_index(unit_id)?;
+        let unit = &self.units[u];
+        if unit.owner != seat {
+            return Err(GameError::NotOwner(unit_id));
+        }
+        if unit.hp <= 0 {
+            return Err(GameError::UnitDead(unit_id));
+        }
+        if unit.is_stunned() {
+            return Err(GameError::Stunned(unit_id));
+        }
+        if let Some(cooldown) = unit.cooldowns.iter().find(|c| c.ability == ability.name) {
+            return Err(GameError::AbilityCoolingDown { turns: cooldown.turns_left });
+        }
+        if uses_attack(ability.kind) && unit.has_attacked {
+            return Err(GameError::AlreadyAttacked(unit_id));
+        }
+        let (class, from) = (unit.class, (unit.x, unit.y));
+
+        let mut cover = 0;
+        let target = match (needs_target(ability.kind), target_id) {
+            (true, None) => return Err(GameError::TargetRequired),
+            (true, Some(id)) => {
+                let t = self.visible_unit_index(seat, id)?;
+                let target = &self.units[t];
+                if target.hp <= 0 {
+                    return Err(GameError::TargetDead(id));
+                }
+                let friendly = self.teams[target.owner] == self.teams[seat];
+                if id == unit_id || friendly != (ability.kind == AbilityKind::Heal) {
+                    return Err(GameError::InvalidTarget(id));
+                }
+                if calculate_distance(from.0, from.1, target.x, target.y) > ability.range {
+                    return Err(GameError::OutOfRange { range: ability.range });
+                }
+                match self.line_of_fire_between(&self.units[u], target) {
+                    LineOfFire::Blocked((x, y)) => return Err(GameError::LineOfFireBlocked { x, y }),
+                    LineOfFire::Cover(_) => cover = COVER_DEFENSE,
+                    LineOfFire::Clear => {}
+                }
+                Some(t)
+            }
+            (false, Some(id)) if id != unit_id => return Err(GameError::InvalidTarget(id)),
+            (false, _) => None,
+        };
+
+        let amount = match (ability.kind, target) {
+            (AbilityKind::Shot, Some(t)) => {
+                let target_terrain = self.terrain[self.units[t].y as usize][self.units[t].x as usize];
+                let own_terrain = self.terrain[from.1 as usize][from.0 as usize];
+                let defense = (self.units[t].defense_bonus() + cover) * DAMAGE_PER_DEFENSE;
+                let bonus = elevation_bonus(own_terrain, target_terrain) - defense;
+                let target_pos = (self.units[t].x, self.units[t].y);
+                let distance = calculate_distance(from.0, from.1, target_pos.0, target_pos.1);
+                let full = apply_falloff(calculate_damage(class, self.units[t].class, target_terrain, bonus), distance);
+                let damage = (full * ability.power / 100).max(MIN_DAMAGE);
+                self.units[t].hp = (self.units[t].hp - damage).max(0);
+                self.units[u].facing = direction_towards(from, target_pos);
+                damage
+            }
+            (AbilityKind::Heal, Some(t)) => {
+                let target = &mut self.units[t];
+                let healed = ability.power.min(get_class_stats(target.class).max_hp - target.hp);
+                target.hp += healed;
+                healed
+            }
+            (AbilityKind::Fortify, _) => {
+                // Wears off as the owner's next turn starts
+                self.units[u].add_ability_effect(EffectKind::Fortified, ability, 0);
+                ability.power
+            }
+            (AbilityKind::Dash, _) => {
+                let unit = &mut self.units[u];
+                unit.movement_left = unit.movement_left.saturating_add(ability.power as u8);
+                ability.power
+            }
+            (AbilityKind::Poison, Some(t)) => {
+                self.units[t].add_ability_effect(EffectKind::Poison, ability, POISON_TURNS);
+                ability.power
+            }
+            (AbilityKind::Stun, Some(t)) => {
+                self.units[t].add_ability_effect(EffectKind::Stun, ability, ability.power as u8);
+                ability.power
+            }
+            _ => unreachable!("targeted abilities always have a target here"),
+        };
+
+        let unit = &mut self.units[u];
+        if uses_attack(ability.kind) {
+            unit.has_attacked = true;
+        }
+        unit.cooldowns.push(AbilityCooldown { ability: ability.name.clone(), turns_left: ability.cooldown });
+
+        if target.is_some() {
+            self.check_last_team_standing();
+        }
+
+        Ok(AbilityAction { unit_id, target_id, ability: ability.clone(), amount })
+    }
+
+    /// Score capture points, check the victory point and round limits, then
+    /// hand the turn over with the next player's units healed and reset.
+    /// Points a seat holds score for its team.
+    pub fn end_turn(&mut self, seat: Seat) -> Result<(), GameError> {
+        self.check_turn(seat)?;
+
+        let mut held = 0;
+        for point in self.capture_points.iter_mut() {
+            let occupied = self.units.iter().any(|u| u.owner == seat && u.hp > 0 && u.x == point.x && u.y == point.y);
+            if occupied {
+                point.owner = Some(seat);
+            }
+            if point.owner == Some(seat) {
+                held += 1;
+            }
+        }
+
+        let team = self.teams[seat];
+        self.points[team] += held * VP_PER_CAPTURE_POINT;
+        if self.points[team] >= VICTORY_POINT_TARGET {
+            self.finish(Some(team));
+            return Ok(());
+        }
+
+        let next_player = self.next_seat(seat);
+        if self.round >= ROUND_LIMIT && next_player <= seat {
+            // Most points wins; a shared lead is a draw
+            let best = self.points.iter().copied().max().unwrap_or(0);
+            let leaders: Vec<Team> = (0..self.points.len()).filter(|&t| self.points[t] == best).collect();
+            self.finish(if leaders.len() == 1 { Some(leaders[0]) } else { None });
+            return Ok(());
+        }
+
+        self.pass_turn(seat);
+        Ok(())
+    }
+
+    /// The next seat in order after `seat` that still has units
+    fn next_seat(&self, seat: Seat) -> Seat {
+        let seats = self.teams.len();
+        (1..=seats)
+            .map(|step| (seat + step) % seats)
+            .find(|&s| self.has_living_units(s))
+            .unwrap_or(seat)
+    }
+
+    /// Start the next turn: the next seat in order that still has units
+    /// goes, with its units healed, their effects ticked and reset
+    fn pass_turn(&mut self, seat: Seat) {
+        let next_player = self.next_seat(seat);
+        if next_player <= seat {
+            self.round += 1;
+        }
+        self.turn_number += 1;
+        self.current_turn = next_player;
+        for unit in self.units.iter_mut().filter(|u| u.owner == next_player && u.hp > 0) {
+            let stats = get_class_stats(unit.class);
+            if self.objectives[unit.y as usize][unit.x as usize] == ObjectiveType::HealingStation {
+                unit.hp = (unit.hp + HEALING_STATION_HP).min(stats.max_hp);
+            }
+            if let Some(effect) = terrain_effect(self.terrain[unit.y as usize][unit.x as usize]) {
+                add_effect(&mut unit.effects, effect);
+            }
+            // Poison never finishes a unit off
+            let start = start_turn(&mut unit.effects);
+            unit.hp = (unit.hp - start.poison_damage).max(1);
+            unit.movement_left = if start.stunned {
+                0
+            } else {
+                (stats.movement as i32 - start.movement_penalty).max(0) as u8
+            };
+            unit.has_attacked = false;
+            for cooldown in unit.cooldowns.iter_mut() {
+                cooldown.turns_left -= 1;
+            }
+            unit.cooldowns.retain(|c| c.turns_left > 0);
+        }
+    }
+
+    /// Concede: the seat's units are removed from play. In a duel the other
+    /// player wins; otherwise the game goes on without them.
+    pub fn surrender(&mut self, seat: Seat) -> Result<(), GameError> {
+        if self.status != GameStatus::InProgress {
+            return Err(GameError::GameNotInProgress);
+        }
+        if !self.has_living_units(seat) {
+            return Err(GameError::AlreadyEliminated);
+        }
+        for unit in self.units.iter_mut().filter(|u| u.owner == seat) {
+            unit.hp = 0;
+        }
+        self.check_last_team_standing();
+        Ok(())
+    }
+
+    /// Whether a seat has lost all its units
+    pub fn is_eliminated(&self, seat: Seat) -> bool {
+        matches!(self.status, GameStatus::InProgress | GameStatus::Finished) && !self.has_living_units(seat)
+    }
+
+    /// Tiles a seat's team can see: teammates share vision, and the tile a
+    /// unit stands on widens or narrows its own
+    pub fn visible_tiles(&self, seat: Seat) -> Vec<(u8, u8)> {
+        let viewers: Vec<(u8, u8, u8)> = self
+            .units
+            .iter()
+            .filter(|u| self.teams[u.owner] == self.teams[seat] && u.hp > 0)
+            .map(|u| {
+                let vision = vision_from(self.terrain[u.y as usize][u.x as usize], get_class_stats(u.class).vision);
+                (u.x, u.y, vision)
+            })
+            .collect();
+        compute_visible_tiles(&self.terrain, &viewers)
+    }
+
+    fn check_turn(&self, seat: Seat) -> Result<(), GameError> {
+        if self.status != GameStatus::InProgress {
+            return Err(GameError::GameNotInProgress);
+        }
+        if self.current_turn != seat {
+            return Err(GameError::NotYourTurn);
+        }
+        Ok(())
+    }
+
+    /// What stands between two units on the way from one to the other:
+    /// terrain and any other living unit
+    fn line_of_fire_between(&self, from: &UnitState, to: &UnitState) -> LineOfFire {
+        let units: Vec<(u8, u8)> = self
+            .units
+            .iter()
+            .filter(|u| u.hp > 0 && u.id != from.id && u.id != to.id)
+            .map(|u| (u.x, u.y))
+            .collect();
+        line_of_fire(&self.terrain, &units, (from.x, from.y), (to.x, to.y))
+    }
+
+    fn unit_index(&self, unit_id: u32) -> Result<usize, GameError> {
+        self.units
+            .iter()
+            .position(|u| u.id == unit_id)
+            .ok_or(GameError::UnitNotFound(unit_id))
+    }
+
+    /// Like `unit_index`, but units the seat's team can't see are reported as
+    /// not found, so probing ids reveals nothing about where enemies are
+    /// Tiles of the living units `seat` knows about, other than `except`: its
+    /// team's, and other units in its sight
+    pub fn known_unit_tiles(&self, seat: Seat, except: u32) -> Vec<(u8, u8)> {
+        let visible = self.visible_tiles(seat);
+        self.units
+            .iter()
+            .filter(|u| u.id != except && u.hp > 0)
+            .filter(|u| self.teams[u.owner] == self.teams[seat] || visible.contains(&(u.x, u.y)))
+            .map(|u| (u.x, u.y))
+            .collect()
+    }
+
+    fn visible_unit_index(&self, seat: Seat, unit_id: u32) -> Result<usize, GameError> {
+        let index = self.unit_index(unit_id)?;
+        let unit = &self.units[index];
+        if self.teams[unit.owner] != self.teams[seat] && !self.visible_tiles(seat).contains(&(unit.x, unit.y)) {
+            return Err(GameError::UnitNotFound(unit_id));
+        }
+        Ok(index)
+    }
+
+    fn has_living_units(&self, seat: Seat) -> bool {
+        self.units.iter().any(|u| u.owner == seat && u.hp > 0)
+    }
+
+    /// End the game once at most one team has units left, or pass the turn
+    /// on if the player whose turn it is has just been eliminated. Does
+    /// nothing outside of a battle, so it's safe to run again on a loaded state.
+    pub fn check_last_team_standing(&mut self) {
+        if self.status != GameStatus::InProgress {
+            return;
+        }
+        let mut standing: Vec<Team> = (0..self.teams.len())
+            .filter(|&s| self.has_living_units(s))
+            .map(|s| self.teams[s])
+            .collect();
+        standing.sort_unstable();
+        standing.dedup();
+        match standing.len() {
+            0 => self.finish(None),
+            1 => self.finish(Some(standing[0])),
+            _ if !self.has_living_units(self.current_turn) => self.pass_turn(self.current_turn),
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self, winner: Option<Team>) {
+        self.status = GameStatus::Finished;
+        self.winner = winner;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawned(unit_id: u32, class: UnitClass, x: u8, y: u8, facing: Direction) -> SpawnedUnit {
+        SpawnedUnit { unit_id, class, position: Position { x, y }, facing }
+    }
+
+    /// Two single-unit squads on an open 5x5 board with no objectives
+    fn duel(class1: UnitClass, pos1: (u8, u8), class2: UnitClass, pos2: (u8, u8)) -> GameState {
+        let terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        let objectives = vec![vec![ObjectiveType::None; 5]; 5];
+        let mut state = GameState::new(terrain, objectives);
+        state.join(0, &[spawned(1, class1, pos1.0, pos1.1, Direction::South)]).unwrap();
+        state.join(1, &[spawned(2, class2, pos2.0, pos2.1, Direction::North)]).unwrap();
+        state
+    }
+
+    #[test]
+    fn game_starts_when_both_seats_join() {
+        let terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        let objectives = vec![vec![ObjectiveType::None; 5]; 5];
+        let mut state = GameState::new(terrain, objectives);
+        assert_eq!(state.join(1, &[]), Err(GameError::SeatOutOfOrder(1)));
+        state.join(0, &[]).unwrap();
+        assert_eq!(state.status, GameStatus::WaitingForPlayers);
+        state.join(1, &[]).unwrap();
+        assert_eq!(state.status, GameStatus::InProgress);
+    }
+
+    #[test]
+    fn move_spends_movement_and_turns_unit() {
+        let mut state = duel(UnitClass::Infantry, (0, 0), UnitClass::Infantry, (4, 4));
+        let action = state.move_unit(0, 1, Position { x: 2, y: 0 }).unwrap();
+        assert_eq!(action.cost, 4);
+        assert_eq!(action.path, vec![Position { x: 1, y: 0 }, Position { x: 2, y: 0 }]);
+        let unit = &state.units[0];
+        assert_eq!((unit.x, unit.y, unit.movement_left, unit.facing), (2, 0, 2, Direction::East));
+    }
+
+    #[test]
+    fn move_is_validated() {
+        let mut state = duel(UnitClass::Tank, (0, 0), UnitClass::Infantry, (1, 1));
+        assert_eq!(state.move_unit(1, 2, Position { x: 4, y: 3 }), Err(GameError::NotYourTurn));
+        assert_eq!(state.move_unit(0, 2, Position { x: 4, y: 3 }), Err(GameError::NotOwner(2)));
+        assert_eq!(state.move_unit(0, 1, Position { x: 5, y: 0 }), Err(GameError::OutOfBounds { x: 5, y: 0 }));
+        assert_eq!(state.move_unit(0, 1, Position { x: 1, y: 1 }), Err(GameError::Occupied { x: 1, y: 1 }));
+        assert_eq!(
+            state.move_unit(0, 1, Position { x: 3, y: 0 }),
+            Err(GameError::NoPath { x: 3, y: 0, movement: 4 })
+        );
+        assert_eq!(state.units[0].movement_left, 4);
+    }
+
+    #[test]
+    fn moves_run_into_unseen_units_instead_of_being_refused() {
+        // A road along the top row; the enemy is beyond the Infantry's sight
+        let mut terrain = vec![vec![TerrainType::Plains; 7]; 5];
+        terrain[0] = vec![TerrainType::Road; 7];
+        let mut state = GameState::new(terrain, vec![vec![ObjectiveType::None; 7]; 5]);
+        state.join(0, &[spawned(1, UnitClass::Infantry, 0, 0, Direction::South)]).unwrap();
+        state.join(1, &[spawned(2, UnitClass::Scout, 4, 0, Direction::West)]).unwrap();
+        let before = state.clone();
+
+        let action = state.move_unit(0, 1, Position { x: 4, y: 0 }).unwrap();
+        assert_eq!(action.to, Position { x: 3, y: 0 });
+        assert_eq!(action.bumped, Some(Position { x: 4, y: 0 }));
+        assert_eq!(action.cost, 6);
+        let unit = &state.units[0];
+        assert_eq!((unit.x, unit.y, unit.movement_left, unit.facing), (3, 0, 0, Direction::East));
+
+        let mut replayed = before.clone();
+        replayed.apply(0, &ActionPayload::Move(action)).unwrap();
+        assert_eq!(replayed.units, state.units);
+
+        let mut past = before;
+        let action = past.move_unit(0, 1, Position { x: 6, y: 0 }).unwrap();
+        assert_eq!((action.to, action.destination), (Position { x: 3, y: 0 }, Position { x: 6, y: 0 }));
+    }
+
+    #[test]
+    fn end_turn_switches_seat_and_resets_units() {
+        let mut state = duel(UnitClass::Infantry, (0, 0), UnitClass::Infantry, (4, 4));
+        state.move_unit(0, 1, Position { x: 1, y: 0 }).unwrap();
+        state.end_turn(0).unwrap();
+        assert_eq!((state.current_turn, state.turn_number), (1, 2));
+        state.end_turn(1).unwrap();
+        assert_eq!(state.units[0].movement_left, 6);
+    }
+
+    #[test]
+    fn defender_counter_attacks() {
+        let mut state = duel(UnitClass::Infantry, (2, 1), UnitClass::Infantry, (2, 2));
+        let action = state.attack(0, 1, 2).unwrap();
+        // Attacked head-on, so no flanking bonus
+        assert!(!action.flanking);
+        assert_eq!(action.damage, 30);
+        assert_eq!(action.counter_damage, 15);
+        assert_eq!(state.units[0].hp, 85);
+        assert_eq!(state.units[1].hp, 70);
+        assert_eq!(state.attack(0, 1, 2), Err(GameError::AlreadyAttacked(1)));
+    }
+
+    #[test]
+    fn attack_from_behind_flanks() {
+        let mut state = duel(UnitClass::Infantry, (2, 3), UnitClass::Infantry, (2, 2));
+        let preview = state.preview_attack(0, 1, 2).unwrap();
+        let action = state.attack(0, 1, 2).unwrap();
+        assert!(action.flanking);
+        assert_eq!(action.damage, preview.damage);
+        assert_eq!(state.units[1].hp, preview.target_hp_after);
+    }
+
+    #[test]
+    fn attack_is_validated() {
+        let mut state = duel(UnitClass::Infantry, (0, 0), UnitClass::Infantry, (0, 3));
+        assert_eq!(state.attack(0, 1, 2), Err(GameError::OutOfRange { range: 1 }));
+        assert_eq!(state.attack(0, 2, 1), Err(GameError::NotOwner(2)));
+        assert_eq!(state.attack(0, 1, 9), Err(GameError::UnitNotFound(9)));
+    }
+
+    #[test]
+    fn units_out_of_sight_cant_be_targeted() {
+        let state = duel(UnitClass::Tank, (0, 0), UnitClass::Scout, (4, 4));
+        // Hidden units look exactly like ones that don't exist
+        assert_eq!(state.preview_attack(0, 1, 2), Err(GameError::UnitNotFound(2)));
+        assert_eq!(state.preview_attack(0, 1, 9), Err(GameError::UnitNotFound(9)));
+        let shell = ability("shell", AbilityKind::Shot, 10, 50, 2);
+        assert_eq!(state.clone().use_ability(0, 1, &shell, Some(2)), Err(GameError::UnitNotFound(2)));
+    }
+
+    #[test]
+    fn wiping_out_a_squad_wins() {
+        let mut state = duel(UnitClass::Tank, (2, 1), UnitClass::Scout, (2, 2));
+        state.units[1].hp = 10;
+        state.attack(0, 1, 2).unwrap();
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, Some(0));
+        assert_eq!(state.end_turn(0), Err(GameError::GameNotInProgress));
+    }
+
+    #[test]
+    fn last_team_standing_is_checked_again_on_a_loaded_state() {
+        let mut state = duel(UnitClass::Tank, (2, 1), UnitClass::Scout, (2, 2));
+        state.units[1].hp = 0;
+        state.check_last_team_standing();
+        assert_eq!((state.status, state.winner), (GameStatus::Finished, Some(0)));
+
+        let terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        let objectives = vec![vec![ObjectiveType::None; 5]; 5];
+        let mut waiting = GameState::new(terrain, objectives);
+        waiting.check_last_team_standing();
+        assert_eq!(waiting.status, GameStatus::WaitingForPlayers);
+    }
+
+    /// A 7x7 open board for `mode` with one unit per seat
+    fn multiplayer(mode: GameMode, squads: &[(UnitClass, (u8, u8))]) -> GameState {
+        let terrain = vec![vec![TerrainType::Plains; 7]; 7];
+        let objectives = vec![vec![ObjectiveType::None; 7]; 7];
+        let mut state = GameState::new(terrain, objectives).with_teams(seat_teams(&mode));
+        for (seat, &(class, (x, y))) in squads.iter().enumerate() {
+            state.join(seat, &[spawned(seat as u32 + 1, class, x, y, Direction::North)]).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn free_for_all_goes_on_without_eliminated_players() {
+        let squads = [(UnitClass::Tank, (3, 2)), (UnitClass::Scout, (3, 3)), (UnitClass::Infantry, (6, 6))];
+        let mut state = multiplayer(GameMode::FreeForAll(3), &squads);
+        state.units[1].hp = 10;
+        state.attack(0, 1, 2).unwrap();
+        assert_eq!(state.status, GameStatus::InProgress);
+        assert!(state.is_eliminated(1));
+
+        state.end_turn(0).unwrap();
+        assert_eq!(state.current_turn, 2);
+        state.end_turn(2).unwrap();
+        assert_eq!(state.current_turn, 0);
+        assert_eq!(state.surrender(1), Err(GameError::AlreadyEliminated));
+
+        state.surrender(2).unwrap();
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, Some(0));
+    }
+
+    #[test]
+    fn teammates_share_vision_and_victory() {
+        let squads = [
+            (UnitClass::Infantry, (3, 3)),
+            (UnitClass::Infantry, (3, 4)),
+            (UnitClass::Infantry, (6, 5)),
+            (UnitClass::Scout, (6, 6)),
+        ];
+        let mut state = multiplayer(GameMode::Teams, &squads);
+        assert!(state.visible_tiles(0).contains(&(6, 6)));
+        assert_eq!(state.preview_attack(0, 1, 3), Err(GameError::FriendlyFire(3)));
+
+        state.units[1].hp = 10;
+        state.units[3].hp = 10;
+        state.attack(0, 1, 2).unwrap();
+        assert_eq!(state.status, GameStatus::InProgress);
+        state.end_turn(0).unwrap();
+        assert_eq!(state.current_turn, 2);
+        state.attack(2, 3, 4).unwrap();
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, Some(0));
+    }
+
+    #[test]
+    fn holding_capture_points_wins_on_points() {
+        let mut state = duel(UnitClass::Infantry, (2, 2), UnitClass::Infantry, (4, 4));
+        state.objectives[2][2] = ObjectiveType::CapturePoint;
+        state.capture_points.push(CaptureState { x: 2, y: 2, owner: None });
+        state.points[0] = VICTORY_POINT_TARGET - 1;
+        state.end_turn(0).unwrap();
+        assert_eq!(state.capture_points[0].owner, Some(0));
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, Some(0));
+    }
+
+    #[test]
+    fn round_limit_with_equal_points_is_a_draw() {
+        let mut state = duel(UnitClass::Infantry, (0, 0), UnitClass::Infantry, (4, 4));
+        state.round = ROUND_LIMIT;
+        state.end_turn(0).unwrap();
+        assert_eq!(state.status, GameStatus::InProgress);
+        state.end_turn(1).unwrap();
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, None);
+    }
+
+    #[test]
+    fn rounds_skip_eliminated_seats() {
+        let squads = [(UnitClass::Infantry, (0, 0)), (UnitClass::Infantry, (3, 3)), (UnitClass::Infantry, (6, 6))];
+        let mut state = multiplayer(GameMode::FreeForAll(3), &squads);
+        state.units[1].hp = 0;
+        state.end_turn(0).unwrap();
+        state.end_turn(2).unwrap();
+        assert_eq!((state.round, state.turn_number), (2, 3));
+
+        state.round = ROUND_LIMIT;
+        state.end_turn(0).unwrap();
+        assert_eq!(state.status, GameStatus::InProgress);
+        state.end_turn(2).unwrap();
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, None);
+    }
+
+    #[test]
+    fn server_timeouts_replay_like_player_actions() {
+        let mut state = duel(UnitClass::Infantry, (0, 0), UnitClass::Infantry, (4, 4));
+        state.apply(0, &ActionPayload::Timeout).unwrap();
+        assert_eq!(state.current_turn, 1);
+        state.apply(1, &ActionPayload::Forfeit).unwrap();
+        assert_eq!(state.status, GameStatus::Finished);
+        assert_eq!(state.winner, Some(0));
+    }
+
+    #[test]
+    fn apply_reports_divergence() {
+        let mut state = duel(UnitClass::Infantry, (2, 1), UnitClass::Infantry, (2, 2));
+        let logged = AttackAction {
+            attacker_id: 1,
+            target_id: 2,
+            damage: 99,
+            flanking: false,
+            counter_damage: 15,
+            range: 1,
+            obstruction: None,
+        };
+        let err = state.apply(0, &ActionPayload::Attack(logged));
+        assert!(matches!(err, Err(GameError::Diverged(_))));
+    }
+
+    fn ability(name: &str, kind: AbilityKind, range: u8, power: i32, cooldown: u8) -> AbilitySpec {
+        AbilitySpec { name: name.to_string(), kind, range, power, cooldown }
+    }
+
+    #[test]
+    fn shot_skips_counter_and_cools_down() {
+        let shell = ability("shell", AbilityKind::Shot, 3, 50, 2);
+        let mut state = duel(UnitClass::Tank, (0, 0), UnitClass::Infantry, (0, 2));
+        // Half of the Tank's 45 damage after falloff over two tiles
+        let action = state.use_ability(0, 1, &shell, Some(2)).unwrap();
+        assert_eq!(action.amount, 22);
+        assert_eq!((state.units[0].hp, state.units[1].hp), (120, 78));
+        assert!(state.units[0].has_attacked);
+        assert_eq!(state.use_ability(0, 1, &shell, Some(2)), Err(GameError::AbilityCoolingDown { turns: 2 }));
+
+        state.end_turn(0).unwrap();
+        state.end_turn(1).unwrap();
+        assert_eq!(state.use_ability(0, 1, &shell, Some(2)), Err(GameError::AbilityCoolingDown { turns: 1 }));
+        state.end_turn(0).unwrap();
+        state.end_turn(1).unwrap();
+        assert!(state.use_ability(0, 1, &shell, Some(2)).is_ok());
+
+        let mut replay = duel(UnitClass::Tank, (0, 0), UnitClass::Infantry, (0, 2));
+        replay.apply(0, &ActionPayload::Ability(action.clone())).unwrap();
+        let mut replay = duel(UnitClass::Tank, (0, 0), UnitClass::Infantry, (0, 2));
+        let tampered = AbilityAction { amount: 99, ..action };
+        assert!(matches!(replay.apply(0, &ActionPayload::Ability(tampered)), Err(GameError::Diverged(_))));
+    }
+
+    #[test]
+    fn targeted_abilities_need_line_of_fire() {
+        let dart = ability("poison_dart", AbilityKind::Poison, 2, 10, 3);
+        let mut state = duel(UnitClass::Scout, (2, 0), UnitClass::Infantry, (2, 2));
+        state.terrain[1][2] = TerrainType::Mountain;
+        // A second unit keeps the target in sight behind the Mountain
+        state.units.push(UnitState { id: 3, x: 4, y: 2, ..state.units[0].clone() });
+        assert_eq!(state.use_ability(0, 1, &dart, Some(2)), Err(GameError::LineOfFireBlocked { x: 2, y: 1 }));
+        state.terrain[1][2] = TerrainType::Plains;
+        assert!(state.use_ability(0, 1, &dart, Some(2)).is_ok());
+    }
+
+    #[test]
+    fn heal_targets_allies_and_fortify_lasts_a_round() {
+        let first_aid = ability("first_aid", AbilityKind::Heal, 1, 25, 3);
+        let fortify = ability("fortify", AbilityKind::Fortify, 0, 1, 2);
+        let terrain = vec![vec![TerrainType::Plains; 5]; 5];
+        let objectives = vec![vec![ObjectiveType::None; 5]; 5];
+        let mut state = GameState::new(terrain, objectives);
+        let squad = [spawned(1, UnitClass::Infantry, 1, 1, Direction::South), spawned(2, UnitClass::Infantry, 1, 2, Direction::South)];
+        state.join(0, &squad).unwrap();
+        state.join(1, &[spawned(3, UnitClass::Tank, 2, 1, Direction::West)]).unwrap();
+
+        assert_eq!(state.use_ability(0, 1, &first_aid, Some(3)), Err(GameError::InvalidTarget(3)));
+        assert_eq!(state.use_ability(0, 1, &first_aid, Some(1)), Err(GameError::InvalidTarget(1)));
+        assert_eq!(state.use_ability(0, 1, &first_aid, None), Err(GameError::TargetRequired));
+        state.units[1].hp = 60;
+        assert_eq!(state.use_ability(0, 1, &first_aid, Some(2)).unwrap().amount, 25);
+        assert_eq!(state.units[1].hp, 85);
+
+        assert_eq!(state.preview_attack(1, 3, 1).unwrap().damage, 60);
+        state.use_ability(0, 1, &fortify, None).unwrap();
+        assert_eq!(state.preview_attack(1, 3, 1).unwrap().damage, 50);
+        state.end_turn(0).unwrap();
+        state.end_turn(1).unwrap();
+        assert_eq!(state.preview_attack(1, 3, 1).unwrap().damage, 60);
+    }
+
+    #[test]
+    fn stunned_units_sit_out_their_next_turn() {
+        let concussion = ability("concussion", AbilityKind::Stun, 1, 1, 4);
+        let mut state = duel(UnitClass::Tank, (2, 1), UnitClass::Infantry, (2, 2));
+        state.use_ability(0, 1, &concussion, Some(2)).unwrap();
+        state.units[0].has_attacked = false;
+        assert_eq!(state.preview_attack(0, 1, 2).unwrap().counter_damage, 0);
+
+        state.end_turn(0).unwrap();
+        assert_eq!(state.units[1].movement_left, 0);
+        assert_eq!(state.move_unit(1, 2, Position { x: 3, y: 2 }), Err(GameError::Stunned(2)));
+        assert_eq!(state.attack(1, 2, 1), Err(GameError::Stunned(2)));
+        state.end_turn(1).unwrap();
+        state.end_turn(0).unwrap();
+        assert_eq!(state.units[1].movement_left, 6);
+        assert!(state.units[1].effects.is_empty());
+    }
+
+    #[test]
+    fn poison_and_swamps_wear_units_down() {
+        let dart = ability("poison_dart", AbilityKind::Poison, 2, 10, 3);
+        let mut state = duel(UnitClass::Scout, (2, 0), UnitClass::Infantry, (2, 2));
+        state.terrain[0][3] = TerrainType::Swamp;
+        state.units[1].hp = 25;
+        state.use_ability(0, 1, &dart, Some(2)).unwrap();
+        // Poison stops at 1 HP and runs out after three turns
+        for hp in [15, 5, 1, 1] {
+            state.end_turn(0).unwrap();
+            assert_eq!(state.units[1].hp, hp);
+            state.end_turn(1).unwrap();
+        }
+
+        state.move_unit(0, 1, Position { x: 3, y: 0 }).unwrap();
+        state.end_turn(0).unwrap();
+        state.end_turn(1).unwrap();
+        assert_eq!(state.units[0].movement_left, 6);
+        assert_eq!(state.units[0].effects[0].source, EffectSource::Terrain(TerrainType::Swamp));
+    }
+
+    #[test]
+    fn archers_shoot_over_cover_but_not_through_mountains() {
+        let mut state = duel(UnitClass::Archer, (2, 0), UnitClass::Infantry, (2, 3));
+        // Falloff takes 20% off at three tiles, and melee can't shoot back
+        let outcome = state.preview_attack(0, 1, 2).unwrap();
+        assert_eq!((outcome.damage, outcome.range, outcome.counter_damage), (20, 3, 0));
+
+        // The Forest hides the target, so a second unit has to spot it
+        state.terrain[1][2] = TerrainType::Forest;
+        assert_eq!(state.preview_attack(0, 1, 2), Err(GameError::UnitNotFound(2)));
+        state.units.push(UnitState { id: 3, x: 4, y: 3, ..state.units[0].clone() });
+        let outcome = state.preview_attack(0, 1, 2).unwrap();
+        assert_eq!((outcome.damage, outcome.obstruction), (12, Some((2, 1))));
+
+        state.terrain[1][2] = TerrainType::Mountain;
+        assert_eq!(state.attack(0, 1, 2), Err(GameError::LineOfFireBlocked { x: 2, y: 1 }));
+        // Units in the way stop the shot too, friend or foe
+        state.terrain[1][2] = TerrainType::Plains;
+        state.units.push(UnitState { id: 4, y: 2, ..state.units[0].clone() });
+        assert_eq!(state.attack(0, 1, 2), Err(GameError::LineOfFireBlocked { x: 2, y: 2 }));
+    }
+}
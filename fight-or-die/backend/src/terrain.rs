@@ -0,0 +1,73 @@
+//! Terrain as data: what each tile type does to movement, sight and combat.
+//! `rules.rs`, the board generator and the bot all read these properties,
+//! so a new tile type only needs a row here and a character in `board.rs`.
+
+use crate::TerrainType;
+
+/// Extra damage per level of height an attacker has over its target
+pub const ELEVATION_BONUS: i32 = 5;
+
+/// Properties of one terrain type. Not a table - like class stats, only
+/// ever read by the rules.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainProperties {
+    pub defense: i32,        // Damage taken drops by this times DAMAGE_PER_DEFENSE; negative adds damage
+    pub move_cost: u8,       // Movement points to enter; unused when impassable
+    pub vision_modifier: i8, // Added to the vision of a unit standing here
+    pub blocks_sight: bool,  // Hides what's behind it; shots passing over it give the target cover
+    pub blocks_shots: bool,  // Stops ranged attacks passing over it
+    pub passable: bool,      // Whether units can enter and stand on it
+    pub height: u8,          // Attacking from higher up adds ELEVATION_BONUS per level
+}
+
+/// Properties of each terrain type. Rebalance here, not in the rules. Move
+/// costs count in half steps, so a Road can cost half a Plains tile.
+pub fn terrain_properties(terrain: TerrainType) -> TerrainProperties {
+    let properties = |defense, move_cost, vision_modifier, blocks_sight, blocks_shots, passable, height| {
+        TerrainProperties { defense, move_cost, vision_modifier, blocks_sight, blocks_shots, passable, height }
+    };
+    match terrain {
+        TerrainType::Plains => properties(0, 2, 0, false, false, true, 0),
+        TerrainType::Forest => properties(1, 4, -1, true, false, true, 0),
+        TerrainType::Mountain => properties(2, 6, 1, true, true, true, 2),
+        TerrainType::Swamp => properties(0, 4, 0, false, false, true, 0),
+        TerrainType::Water => properties(0, 0, 0, false, false, false, 0),
+        TerrainType::River => properties(-1, 6, 0, false, false, true, 0),
+        TerrainType::Road => properties(0, 1, 0, false, false, true, 0),
+        TerrainType::Ruins => properties(1, 4, 0, false, false, true, 1),
+    }
+}
+
+/// Damage bonus for attacking a unit on `to` from `from`: nothing on the
+/// level or uphill
+pub fn elevation_bonus(from: TerrainType, to: TerrainType) -> i32 {
+    let from_height = terrain_properties(from).height as i32;
+    let to_height = terrain_properties(to).height as i32;
+    (from_height - to_height).max(0) * ELEVATION_BONUS
+}
+
+/// Vision radius of a unit with `vision` standing on `terrain`
+pub fn vision_from(terrain: TerrainType, vision: u8) -> u8 {
+    (vision as i32 + terrain_properties(terrain).vision_modifier as i32).max(0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attacking_downhill_hits_harder() {
+        assert_eq!(elevation_bonus(TerrainType::Mountain, TerrainType::Plains), 2 * ELEVATION_BONUS);
+        assert_eq!(elevation_bonus(TerrainType::Mountain, TerrainType::Ruins), ELEVATION_BONUS);
+        assert_eq!(elevation_bonus(TerrainType::Plains, TerrainType::Mountain), 0);
+        assert_eq!(elevation_bonus(TerrainType::Forest, TerrainType::Road), 0);
+    }
+
+    #[test]
+    fn standing_ground_changes_vision() {
+        assert_eq!(vision_from(TerrainType::Mountain, 3), 4);
+        assert_eq!(vision_from(TerrainType::Forest, 3), 2);
+        assert_eq!(vision_from(TerrainType::Forest, 0), 0);
+        assert!(!terrain_properties(TerrainType::Water).passable);
+    }
+}
@@ -0,0 +1,117 @@
+//! Seeding and pairings for tournaments. Players are referred to by index
+//! (seed order or standings order); `lib.rs` maps them to identities.
+
+/// Tournament points for winning a match (or getting a bye)
+pub const WIN_POINTS: u32 = 2;
+/// Tournament points for a drawn match
+pub const DRAW_POINTS: u32 = 1;
+/// Fewest players a tournament can start with
+pub const MIN_TOURNAMENT_PLAYERS: usize = 2;
+
+/// Seed order for players with these ratings: indices of the highest rating
+/// first, ties going to whoever registered first
+pub fn seed_by_rating(ratings: &[i32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..ratings.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(ratings[i]));
+    order
+}
+
+/// Rounds needed to reduce `players` to one winner. Swiss tournaments play
+/// the same number of rounds.
+pub fn round_count(players: usize) -> u32 {
+    players.max(1).next_power_of_two().trailing_zeros()
+}
+
+/// First-round matches of a single-elimination bracket, as seeds (0 is the
+/// top seed). The bracket is padded to a power of two and the top seeds get
+/// byes (`None`). Winners of matches 2k and 2k+1 meet in the next round, and
+/// the top two seeds can only meet in the final.
+pub fn elimination_pairings(players: usize) -> Vec<(usize, Option<usize>)> {
+    let size = players.max(2).next_power_of_two();
+    let mut order = vec![0];
+    while order.len() < size {
+        let len = order.len();
+        order = order.iter().flat_map(|&seed| [seed, 2 * len - 1 - seed]).collect();
+    }
+    order
+        .chunks(2)
+        .map(|pair| (pair[0], if pair[1] < players { Some(pair[1]) } else { None }))
+        .collect()
+}
+
+/// Where a player stands in a Swiss tournament
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub seed: usize,
+    pub score: u32,
+    pub opponents: Vec<usize>, // Seeds already played
+    pub had_bye: bool,
+}
+
+/// Standings order: highest score first, then the better seed
+pub fn swiss_ranking(standings: &[Standing]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..standings.len()).collect();
+    order.sort_by_key(|&i| (std::cmp::Reverse(standings[i].score), standings[i].seed));
+    order
+}
+
+/// Next Swiss round, as indices into `standings`. With an odd number of
+/// players the lowest-ranked player without a bye sits out (`None`). Everyone
+/// else is paired down the standings with the next player they haven't met;
+/// a rematch only happens when nobody else is left.
+pub fn swiss_pairings(standings: &[Standing]) -> Vec<(usize, Option<usize>)> {
+    let mut unpaired = swiss_ranking(standings);
+    let mut pairings = Vec::new();
+
+    if unpaired.len() % 2 == 1 {
+        let pos = unpaired.iter().rposition(|&i| !standings[i].had_bye).unwrap_or(unpaired.len() - 1);
+        pairings.push((unpaired.remove(pos), None));
+    }
+
+    while !unpaired.is_empty() {
+        let player = unpaired.remove(0);
+        let pos = unpaired
+            .iter()
+            .position(|&i| !standings[player].opponents.contains(&standings[i].seed))
+            .unwrap_or(0);
+        pairings.push((player, Some(unpaired.remove(pos))));
+    }
+    pairings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(seed: usize, score: u32, opponents: Vec<usize>, had_bye: bool) -> Standing {
+        Standing { seed, score, opponents, had_bye }
+    }
+
+    #[test]
+    fn seeds_by_rating_then_registration() {
+        assert_eq!(seed_by_rating(&[1200, 1350, 1100, 1350]), vec![1, 3, 0, 2]);
+        assert_eq!(round_count(2), 1);
+        assert_eq!(round_count(5), 3);
+        assert_eq!(round_count(8), 3);
+    }
+
+    #[test]
+    fn bracket_keeps_top_seeds_apart_and_gives_them_byes() {
+        assert_eq!(elimination_pairings(2), vec![(0, Some(1))]);
+        assert_eq!(elimination_pairings(8), vec![(0, Some(7)), (3, Some(4)), (1, Some(6)), (2, Some(5))]);
+        assert_eq!(elimination_pairings(5), vec![(0, None), (3, Some(4)), (1, None), (2, None)]);
+    }
+
+    #[test]
+    fn swiss_avoids_rematches_and_repeat_byes() {
+        // Round 1 was 0-1, 2-3 and a bye for 4. 0 and 2 won.
+        let standings = vec![
+            standing(0, 2, vec![1], false),
+            standing(1, 0, vec![0], false),
+            standing(2, 2, vec![3], false),
+            standing(3, 0, vec![2], false),
+            standing(4, 2, vec![], true),
+        ];
+        assert_eq!(swiss_pairings(&standings), vec![(3, None), (0, Some(2)), (4, Some(1))]);
+    }
+}